@@ -1,13 +1,16 @@
 use std::env;
 use std::error::Error;
 use std::fmt::Debug;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use serenity::model::prelude::*;
 use serenity::model::application::interaction::Interaction;
 use serenity::prelude::*;
 
-use battleships_impl::hooks;
+use battleships_impl::{hooks, options};
+use battleships_impl::store::GameStore;
 
 struct Handler {
 	user_id: AtomicU64
@@ -38,8 +41,11 @@ impl EventHandler for Handler {
 	async fn message(&self, ctx: Context, new_message: Message) {
 		let user_id = UserId(self.user_id.load(Ordering::Relaxed));
 		if !new_message.mentions_user_id(user_id) { return; }
-		if let Some(other_player) = to_scalar(new_message.mentions.iter().filter(|&u| u.id != user_id)) {
-			generic_handler(hooks::start_game(&ctx, new_message.channel_id, &new_message.author, other_player).await);
+		let opponents: Vec<&User> = options::order_mentions(&new_message.content, &new_message.mentions).into_iter()
+			.filter(|u| u.id != user_id)
+			.collect();
+		if !opponents.is_empty() {
+			generic_handler(hooks::start_game(&ctx, new_message.channel_id, &new_message.author, &opponents, &new_message.content).await);
 		}
 	}
 
@@ -52,13 +58,6 @@ impl EventHandler for Handler {
 	}
 }
 
-fn to_scalar<T>(mut iter: impl Iterator<Item = T>) -> Option<T> {
-	match iter.next() {
-		Some(v) if iter.next().is_none() => Some(v),
-		_ => None
-	}
-}
-
 fn generic_handler<T, E: Error + Debug>(result: Result<T, E>) {
 	if let Err(err) = result {
 		dbg!(err);
@@ -74,8 +73,29 @@ async fn main() {
 		| GatewayIntents::DIRECT_MESSAGES
 		| GatewayIntents::MESSAGE_CONTENT;
 
+	// Games are kept in memory, unless a directory is given to save them in
+	let store = match env::var("GAME_DIR") {
+		Ok(dir) => GameStore::with_dir(dir).expect("Error creating the game directory."),
+		Err(_) => GameStore::new()
+	};
+	let store = Arc::new(store);
+
+	// Games nobody plays anymore are dropped once a day
+	let idle = store.clone();
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+		loop {
+			interval.tick().await;
+			let store = idle.clone();
+			if let Err(err) = tokio::task::spawn_blocking(move || store.evict_idle(GameStore::IDLE_LIMIT)).await {
+				println!("Failed to evict idle games: {err}");
+			}
+		}
+	});
+
 	let mut builder = Client::builder(token, intents)
 		.event_handler(Handler::new())
+		.type_map_insert::<GameStore>(store)
 		.await
 		.expect("Error creating client.");
 
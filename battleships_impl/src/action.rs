@@ -1,14 +1,36 @@
+use std::fmt::{Display, Formatter};
+
 use base64::{Engine as _, engine::general_purpose::STANDARD_NO_PAD};
 
-use battleships_model::encode::Encode;
-use battleships_model::game_state::{GameState, Vec2, GRID_SIZE};
+use battleships_model::encode::{DecodeError, Format};
+use battleships_model::game_state::{BoardSize, GameState, Rotation, Turn, Vec2};
+use battleships_model::movement::ShipMove;
+use battleships_model::weapon::Weapon;
 
-use crate::consts::CUSTOM_ID_PREFIX;
+use crate::consts::{CUSTOM_ID_PREFIX, MAX_CUSTOM_ID_LEN};
+use crate::store::{Game, GameKey};
 
 #[derive(Debug, Clone)]
 pub struct GameAction {
 	pub kind: GameActionKind,
-	pub state: GameState
+	pub game: Game
+}
+
+/// A parsed custom id, which still has to be looked up in the [`GameStore`](crate::store::GameStore).
+#[derive(Debug, Clone)]
+pub struct GameActionRef {
+	pub kind: GameActionKind,
+	pub game: GameRef,
+	/// The player a [`GameActionKind::ChooseTarget`] button aims at.
+	pub target: Option<Turn>
+}
+
+/// The game a custom id refers to.
+#[derive(Debug, Clone)]
+pub enum GameRef {
+	Stored(GameKey),
+	/// Buttons from before the games were stored carry the entire state in [`Format::V1`].
+	Legacy { data: Vec<u8>, state: GameState }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -17,32 +39,56 @@ pub enum GameActionKind {
 	Fire,
 	Place,
 	RandomizePlace,
-	ConfirmPlace
+	ArrangeShip,
+	ConfirmPlace,
+	ChooseTarget,
+	Sonar,
+	Airstrike,
+	Cluster,
+	MoveShip,
+	DepthCharge
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum GameActionParseError {
 	NotBattleshipId,
 	UnknownAction,
-	InvalidData,
+	/// The key or state isn't valid base64.
+	InvalidEncoding,
+	InvalidData(DecodeError),
 	NoData
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct Coord(pub Vec2);
 
+#[derive(Debug, Copy, Clone)]
+pub struct Direction(pub Rotation);
+
+#[derive(Debug, Copy, Clone)]
+pub struct Movement(pub ShipMove);
+
 impl GameAction {
-	pub fn new(kind: GameActionKind, state: GameState) -> Self {
-		Self { kind, state }
+	pub fn new(kind: GameActionKind, game: Game) -> Self {
+		Self { kind, game }
 	}
 
+	/// Only the key of the game goes into the id. Target buttons add the player they aim at.
 	pub fn to_id(self) -> String {
+		let mut data = self.game.key.to_bytes().to_vec();
+		if let GameActionKind::ChooseTarget = self.kind {
+			data.push(self.game.target_turn().num());
+		}
+
 		let mut id = CUSTOM_ID_PREFIX.to_owned();
 		id.push(self.kind.to_char());
-		STANDARD_NO_PAD.encode_string(self.state.encode(), &mut id);
+		STANDARD_NO_PAD.encode_string(data, &mut id);
+		debug_assert!(id.len() <= MAX_CUSTOM_ID_LEN);
 		id
 	}
+}
 
+impl GameActionRef {
 	pub fn from_id(mut id: &str) -> Result<Self, GameActionParseError> {
 		if !id.starts_with(CUSTOM_ID_PREFIX) {
 			return Err(GameActionParseError::NotBattleshipId);
@@ -54,9 +100,22 @@ impl GameAction {
 
 		let kind = id.chars().nth(0).ok_or(GameActionParseError::NoData)?;
 		let kind = GameActionKind::from_char(kind)?;
-		let state = parse_game_state(&id[1..])?;
+		let raw = STANDARD_NO_PAD.decode(&id[1..]).map_err(|_| GameActionParseError::InvalidEncoding)?;
 
-		Ok(GameAction::new(kind, state))
+		// Encoded states are always longer than a key
+		let (game, target) = match raw.split_at_checked(GameKey::LEN) {
+			Some((key, rest)) if rest.len() <= 1 => {
+				let key = GameKey::from_bytes(key).ok_or(GameActionParseError::NoData)?;
+				let target = rest.first().and_then(|&num| Turn::new(num, GameState::MAX_PLAYERS));
+				(GameRef::Stored(key), target)
+			}
+			_ => {
+				let state = parse_game_state(&raw, kind)?;
+				(GameRef::Legacy { data: raw, state }, None)
+			}
+		};
+
+		Ok(GameActionRef { kind, game, target })
 	}
 }
 
@@ -67,7 +126,14 @@ impl GameActionKind {
 			GameActionKind::Fire => 'F',
 			GameActionKind::Place => 'P',
 			GameActionKind::RandomizePlace => 'p',
-			GameActionKind::ConfirmPlace => 'C'
+			GameActionKind::ArrangeShip => 'A',
+			GameActionKind::ConfirmPlace => 'C',
+			GameActionKind::ChooseTarget => 't',
+			GameActionKind::Sonar => 's',
+			GameActionKind::Airstrike => 'r',
+			GameActionKind::Cluster => 'c',
+			GameActionKind::MoveShip => 'M',
+			GameActionKind::DepthCharge => 'D'
 		}
 	}
 
@@ -77,64 +143,129 @@ impl GameActionKind {
 			'F' => Ok(GameActionKind::Fire),
 			'P' => Ok(GameActionKind::Place),
 			'p' => Ok(GameActionKind::RandomizePlace),
+			'A' => Ok(GameActionKind::ArrangeShip),
 			'C' => Ok(GameActionKind::ConfirmPlace),
+			't' => Ok(GameActionKind::ChooseTarget),
+			's' => Ok(GameActionKind::Sonar),
+			'r' => Ok(GameActionKind::Airstrike),
+			'c' => Ok(GameActionKind::Cluster),
+			'M' => Ok(GameActionKind::MoveShip),
+			'D' => Ok(GameActionKind::DepthCharge),
 			_ => Err(GameActionParseError::UnknownAction)
 		}
 	}
 }
 
+impl GameActionKind {
+	/// Whether the action arranges a board before the battle.
+	pub fn is_placement(self) -> bool {
+		matches!(self, GameActionKind::Place | GameActionKind::RandomizePlace | GameActionKind::ArrangeShip | GameActionKind::ConfirmPlace)
+	}
+
+	pub fn from_weapon(weapon: Weapon) -> GameActionKind {
+		match weapon {
+			Weapon::Sonar => GameActionKind::Sonar,
+			Weapon::Airstrike => GameActionKind::Airstrike,
+			Weapon::Cluster => GameActionKind::Cluster
+		}
+	}
+
+	pub fn weapon(self) -> Option<Weapon> {
+		match self {
+			GameActionKind::Sonar => Some(Weapon::Sonar),
+			GameActionKind::Airstrike => Some(Weapon::Airstrike),
+			GameActionKind::Cluster => Some(Weapon::Cluster),
+			_ => None
+		}
+	}
+}
+
 impl Coord {
-	pub fn from_str(t: &str) -> Option<Coord> {
+	pub fn parse(t: &str, board_size: BoardSize) -> Option<Coord> {
 		let mut chrs = t.chars();
 		if let Some(column) = chrs.next() {
 			return if !column.is_ascii() { None }
-			else { Self::from_pair(column, &t[1..]) }
+			else { Self::from_pair(column, &t[1..], board_size) }
 		}
 
 		None
 	}
 
-	pub fn to_string(self) -> String {
-		const UP_A: u32 = 'A' as u32;
-
-		let mut res = String::new();
-		res.push(char::from_u32(UP_A + u32::from(self.0.x)).unwrap_or('?'));
-		res.push_str(&(self.0.y + 1).to_string());
-		res
-	}
-
-	fn from_pair(column: char, row: &str) -> Option<Coord> {
-		// J = Column 10
+	fn from_pair(column: char, row: &str, board_size: BoardSize) -> Option<Coord> {
 		const UP_A: u32 = 'A' as u32;
-		const UP_J: u32 = 'J' as u32;
-
 		const LOW_A: u32 = 'a' as u32;
-		const LOW_J: u32 = 'j' as u32;
+
+		let size = u32::from(board_size.get());
 
 		let column: u32 = column.into();
 		let column =
-			if column >= UP_A && column <= UP_J { column - UP_A }
-			else if column >= LOW_A && column <= LOW_J { column - LOW_A }
+			if (UP_A..UP_A + size).contains(&column) { column - UP_A }
+			else if (LOW_A..LOW_A + size).contains(&column) { column - LOW_A }
 			else { return None } as u8;
 
 		if let Ok(row) = row.parse::<u8>() {
-			if row >= 1 && row <= GRID_SIZE {
+			if (1..=board_size.get()).contains(&row) {
 				return Some(Coord(Vec2::new(column, row - 1)));
 			}
 		}
-		
+
 		None
 	}
 }
 
-fn parse_game_state(id: &str) -> Result<GameState, GameActionParseError> {
-	if let Ok(raw) = STANDARD_NO_PAD.decode(id) {
-		// This will implicitly do a length check
-		// So if the vector is the wrong size, it will fail
-		if let Some(state) = GameState::try_decode(&raw) {
-			return Ok(state);
+impl Display for Coord {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		const UP_A: u32 = 'A' as u32;
+
+		let column = char::from_u32(UP_A + u32::from(self.0.x)).unwrap_or('?');
+		write!(f, "{}{}", column, self.0.y + 1)
+	}
+}
+
+impl Direction {
+	/// Parses either H/V or the amount of quarter turns (0-3), optionally followed by M to mirror.
+	pub fn parse(t: &str) -> Option<Direction> {
+		let t = t.trim().to_ascii_uppercase();
+		let mut chars = t.chars();
+		let turns = match chars.next()? {
+			'H' => return Some(Direction(Rotation::HORI)),
+			'V' => return Some(Direction(Rotation::VERT)),
+			c @ '0'..='3' => c as u8 - b'0',
+			_ => return None
+		};
+
+		match chars.as_str().trim() {
+			"" => Some(Direction(Rotation::new(turns, false))),
+			"M" => Some(Direction(Rotation::new(turns, true))),
+			_ => None
 		}
 	}
+}
+
+impl Display for Direction {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		if self.0 == Rotation::HORI { f.write_str("H") }
+		else if self.0 == Rotation::VERT { f.write_str("V") }
+		else { write!(f, "{}{}", self.0.quarter_turns(), if self.0.is_mirrored() { "M" } else { "" }) }
+	}
+}
+
+impl Movement {
+	/// Parses U/D/L/R to move a tile, or T to turn. Whole words work as well.
+	pub fn parse(t: &str) -> Option<Movement> {
+		let t = t.trim().to_ascii_lowercase();
+		ShipMove::ALL.into_iter()
+			.find(|m| m.label() == t || m.label()[..1] == t)
+			.map(Movement)
+	}
+}
+
+// Only ids from before the games were stored carry a state, so newer formats are never accepted here
+fn parse_game_state(raw: &[u8], kind: GameActionKind) -> Result<GameState, GameActionParseError> {
+	if Format::detect(raw) != Some(Format::V1) {
+		return Err(GameActionParseError::InvalidData(DecodeError::UnknownVersion));
+	}
 
-	Err(GameActionParseError::InvalidData)
+	// Old ids don't know whether the battle started, but their buttons do
+	GameState::try_decode_v1(raw, !kind.is_placement()).map_err(GameActionParseError::InvalidData)
 }
@@ -4,6 +4,7 @@ pub type SerenityError = serenity::Error;
 pub type SerenityResult<T = ()> = Result<T, SerenityError>;
 
 pub const CUSTOM_ID_PREFIX: &str = "#bs#";
+pub const MAX_CUSTOM_ID_LEN: usize = 100;
 pub const EMBED_COLOR: Color = Color::BLITZ_BLUE;
 pub const RETRY_COLOR: Color = Color::ORANGE;
 pub const ERROR_COLOR: Color = Color::DARK_RED;
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use serenity::model::prelude::*;
 use serenity::model::application::interaction::modal::*;
 use serenity::model::application::interaction::message_component::*;
@@ -6,15 +8,23 @@ use serenity::model::prelude::interaction::InteractionResponseType;
 use serenity::prelude::*;
 
 use battleships_model::game_state::*;
+use battleships_model::movement::MoveReport;
+use battleships_model::phase::{Phase, PhaseError};
+use battleships_model::rules::{GameMode, TurnRule};
+use battleships_model::shot::FireReport;
+use battleships_model::weapon::WeaponReport;
 
 use crate::action::*;
 use crate::consts::*;
+use crate::options::parse_rules;
 use crate::render::*;
+use crate::store::{Game, GameStore, StoreError};
 
 pub async fn handle_component_interaction(ctx: &Context, interaction: &MessageComponentInteraction) -> SerenityResult {
-	match GameAction::from_id(&interaction.data.custom_id) {
-		Ok(action) => {
-			handle_component_game_action(ctx, interaction, action).await
+	match GameActionRef::from_id(&interaction.data.custom_id) {
+		Ok(action) => match load_game(ctx, action).await {
+			Ok(action) => handle_component_game_action(ctx, interaction, action).await,
+			Err(err) => render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, StaleGameRender(err)).await
 		}
 		Err(err) => {
 			dbg!(err);
@@ -24,9 +34,10 @@ pub async fn handle_component_interaction(ctx: &Context, interaction: &MessageCo
 }
 
 pub async fn handle_modal_interaction(ctx: &Context, interaction: &ModalSubmitInteraction) -> SerenityResult {
-	match GameAction::from_id(&interaction.data.custom_id) {
-		Ok(action) => {
-			handle_interaction_game_action(ctx, interaction, action).await
+	match GameActionRef::from_id(&interaction.data.custom_id) {
+		Ok(action) => match load_game(ctx, action).await {
+			Ok(action) => handle_interaction_game_action(ctx, interaction, action).await,
+			Err(err) => render_modal_message(ctx, interaction, StaleGameRender(err)).await
 		}
 		Err(err) => {
 			dbg!(err);
@@ -35,11 +46,58 @@ pub async fn handle_modal_interaction(ctx: &Context, interaction: &ModalSubmitIn
 	}
 }
 
-pub async fn start_game(ctx: &Context, channel_id: ChannelId, player_1: &User, player_2: &User) -> SerenityResult {
-	match check_players(player_1, player_2) {
-		Ok(_) => {
-			let state = GameState::new(player_1.id.0, player_2.id.0);
-			let state = StartRender(state);
+async fn game_store(ctx: &Context) -> Arc<GameStore> {
+	ctx.data.read().await
+		.get::<GameStore>()
+		.cloned()
+		.expect("the game store is registered on startup")
+}
+
+// Looks up the game a button refers to. Buttons from before the store carry the game, which is adopted from then on
+async fn load_game(ctx: &Context, action: GameActionRef) -> std::result::Result<GameAction, StoreError> {
+	let store = game_store(ctx).await;
+	let mut game = match action.game {
+		GameRef::Stored(key) => store.load(key)?,
+		GameRef::Legacy { data, state } => {
+			let game = store.adopt(&data, state)?;
+			persist(store, game.key.id).await;
+			game
+		}
+	};
+
+	if let Some(target) = action.target {
+		game.set_target(target);
+	}
+
+	Ok(GameAction::new(action.kind, game))
+}
+
+async fn create_game(ctx: &Context, state: GameState) -> Game {
+	let store = game_store(ctx).await;
+	let game = store.create(state);
+	persist(store, game.key.id).await;
+	game
+}
+
+async fn save_game(ctx: &Context, game: Game) -> std::result::Result<Game, StoreError> {
+	let store = game_store(ctx).await;
+	let game = store.save(game)?;
+	persist(store, game.key.id).await;
+	Ok(game)
+}
+
+// Writing the file blocks, so it happens on a thread meant for that
+async fn persist(store: Arc<GameStore>, id: u64) {
+	if let Err(err) = tokio::task::spawn_blocking(move || store.persist(id)).await {
+		eprintln!("Failed to persist game {id:016x}: {err}");
+	}
+}
+
+/// Starts a game between the host and every opponent. The host takes the first turn.
+pub async fn start_game(ctx: &Context, channel_id: ChannelId, host: &User, opponents: &[&User], options: &str) -> SerenityResult {
+	match prepare_game(host, opponents, options) {
+		Ok(state) => {
+			let state = StartRender(create_game(ctx, state).await);
 			channel_id.send_message(ctx, |m| state.render_message(m)).await?;
 		}
 		Err(reason) => {
@@ -52,9 +110,9 @@ pub async fn start_game(ctx: &Context, channel_id: ChannelId, player_1: &User, p
 }
 
 async fn handle_component_game_action(ctx: &Context, interaction: &MessageComponentInteraction, mut action: GameAction) -> SerenityResult {
-	if action.state.current().user_id != interaction.user.id.0 {
-		return if action.state.target().user_id == interaction.user.id.0 {
-			// The enemy has clicked
+	if !may_act(&action, interaction.user.id.0) {
+		return if action.game.players().iter().any(|p| p.is_member(interaction.user.id.0)) {
+			// Another player has clicked
 			render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, NotYourTurnRender).await
 		} else {
 			// Some other random user has clicked
@@ -62,31 +120,66 @@ async fn handle_component_game_action(ctx: &Context, interaction: &MessageCompon
 		};
 	}
 
+	if let Err(err) = check_phase(&action) {
+		return render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, WrongPhaseRender(err)).await;
+	}
+
 	match action.kind {
 		GameActionKind::StartTurn => {
-			render_follow_up_and_delete_buttons(ctx, interaction, FireRender(action.state)).await
+			render_follow_up_and_delete_buttons(ctx, interaction, FireRender(action.game)).await
 		}
 		GameActionKind::Fire => {
-			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseFireRender(action.state)).await
+			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseFireRender(action.game)).await
 		}
 		GameActionKind::Place => {
-			render_follow_up_and_delete_buttons(ctx, interaction, PlaceRender(action.state)).await
+			render_follow_up_and_delete_buttons(ctx, interaction, PlaceRender(action.game)).await
 		}
 		GameActionKind::RandomizePlace => {
-			action.state.current_mut().randomize_ships();
-			render_interaction_response(ctx, interaction, InteractionResponseType::UpdateMessage, PlaceRender(action.state)).await
+			if let Err(err) = action.game.randomize_ships() {
+				return render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, WrongPhaseRender(err)).await;
+			}
+			match save_game(ctx, action.game).await {
+				Ok(game) => render_interaction_response(ctx, interaction, InteractionResponseType::UpdateMessage, PlaceRender(game)).await,
+				Err(err) => render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, StaleGameRender(err)).await
+			}
+		}
+		GameActionKind::ArrangeShip => {
+			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseArrangeRender(action.game)).await
 		}
 		GameActionKind::ConfirmPlace => {
-			if action.state.turn_num() == 1 {
-				// If Player 1 chose, we also ask Player 2 to prepare
-				action.state.swap_turn();
-				render_follow_up_and_delete_buttons(ctx, interaction, NextPlaceRender(action.state)).await
-			} else {
-				// If Player 2 confirms, that means both players are ready
-				action.state.swap_turn();
-				render_follow_up_and_delete_buttons(ctx, interaction, FirstTurnRender(action.state)).await
+			let phase = match action.game.confirm_placement() {
+				Ok(phase) => phase,
+				Err(err) => return render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, WrongPhaseRender(err)).await
+			};
+			let game = match save_game(ctx, action.game).await {
+				Ok(game) => game,
+				Err(err) => return render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, StaleGameRender(err)).await
+			};
+
+			match phase {
+				// Ask the next player to prepare as well
+				Phase::AwaitingPlacement(_) => render_follow_up_and_delete_buttons(ctx, interaction, NextPlaceRender(game)).await,
+				// If the last player confirms, that means everyone is ready
+				_ => render_follow_up_and_delete_buttons(ctx, interaction, FirstTurnRender(game)).await
+			}
+		}
+		GameActionKind::ChooseTarget => {
+			// The new target was taken from the button
+			match save_game(ctx, action.game).await {
+				Ok(game) => render_follow_up_and_delete_buttons(ctx, interaction, FireRender(game)).await,
+				Err(err) => render_interaction_response(ctx, interaction, InteractionResponseType::ChannelMessageWithSource, StaleGameRender(err)).await
 			}
 		}
+		GameActionKind::Sonar | GameActionKind::Airstrike | GameActionKind::Cluster => {
+			let Some(weapon) = action.kind.weapon() else { return Ok(()); };
+			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseWeaponRender(action.game, weapon)).await
+		}
+		GameActionKind::MoveShip => {
+			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseMoveRender(action.game)).await
+		}
+		GameActionKind::DepthCharge => {
+			render_interaction_response(ctx, interaction, InteractionResponseType::Modal, ChooseDepthChargeRender(action.game)).await
+		}
 
 		#[allow(unreachable_patterns)]
 		_ => {
@@ -96,6 +189,26 @@ async fn handle_component_game_action(ctx: &Context, interaction: &MessageCompon
 	}
 }
 
+// Buttons and modals only work in the phase they were made for.
+// The phase comes from the stored game, and buttons from before its last change were already turned away as stale
+fn check_phase(action: &GameAction) -> std::result::Result<(), PhaseError> {
+	if action.kind.is_placement() {
+		action.game.phase().check_placement()
+	} else {
+		action.game.phase().check_battle()
+	}
+}
+
+// Teammates may both arrange their shared board, but have to take turns firing
+fn may_act(action: &GameAction, user_id: u64) -> bool {
+	let current = action.game.current();
+	if action.kind.is_placement() {
+		current.is_member(user_id)
+	} else {
+		current.shooter_id() == user_id
+	}
+}
+
 async fn render_interaction_response(ctx: &Context, interaction: &MessageComponentInteraction, kind: InteractionResponseType, state: impl InteractionRender) -> SerenityResult {
 	interaction.create_interaction_response(ctx, |f| f
 		.interaction_response_data(|d| state.render_interaction(d))
@@ -116,53 +229,107 @@ async fn render_follow_up_and_delete_buttons(ctx: &Context, interaction: &Messag
 }
 
 async fn handle_interaction_game_action(ctx: &Context, interaction: &ModalSubmitInteraction, mut action: GameAction) -> SerenityResult {
+	if let Err(err) = check_phase(&action) {
+		return render_modal_message(ctx, interaction, WrongPhaseRender(err)).await;
+	}
+
     match action.kind {
         GameActionKind::Fire => {
 			// Grab the first component. It should be the only one, so we do no more checks.
 			let component = interaction.data.components.iter().flat_map(|v| v.components.iter()).next();
 			let Some(ActionRowComponent::InputText(component)) = component else { return Ok(()); };
-			
-			// Remove the button; if needed, we'll add another
-			interaction.create_interaction_response(ctx, |r| r
-				.interaction_response_data(|d| RemoveButtonsRender.render_interaction(d))
-				.kind(InteractionResponseType::UpdateMessage)
-			).await?;
-
-			let Some(Coord(coord)) = Coord::from_str(&component.value) else {
+
+			let board_size = action.game.rules().board_size;
+			let Some(coords) = component.value
+				.split(|c: char| c.is_whitespace() || c == ',')
+				.filter(|t| !t.is_empty())
+				.map(|t| Coord::parse(t, board_size).map(|c| c.0))
+				.collect::<Option<Vec<_>>>() else {
 				// Invalid coordinate, report to user and let them take another turn
 				return respond_invalid_fire(ctx, interaction, action, InvalidFireReason::InvalidCoord).await;
 			};
 
-			let target = action.state.target_mut();
-			if target.hits.get(coord) {
-				// If the coordinate is already hit, tell the user that and let them take another turn
-				respond_invalid_fire(ctx, interaction, action, InvalidFireReason::AlreadyHit).await?;
-			} else {
-				// Mark the coordinate as hit
-				target.hits.set(coord);
-
-				// Grab the info for the next turn.
-				let next_turn_info = match target.overlap(coord) {
-					Some(ref s) if target.is_sunk(s) => NextTurnInfo::Sunk { kind: s.info.label, loss: target.are_all_ships_sunk() },
-					Some(_) => NextTurnInfo::Hit,
-					None => NextTurnInfo::Miss
-				};
-
-				// Swap turns, and send a message
-				action.state.swap_turn();
-
-				let state = NextTurnRender {
-					state: action.state,
-					tile: Coord(coord),
-					info: next_turn_info
-				};
-
-				interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+			if action.game.rules().turns == TurnRule::Simultaneous {
+				return lock_in(ctx, interaction, action, &coords).await;
 			}
 
-			Ok(())
+			match action.game.fire(&coords) {
+				// If the shots are rejected, tell the user why and let them take another turn
+				Err(err) => respond_invalid_fire(ctx, interaction, action, InvalidFireReason::Rejected(err)).await,
+				Ok(report) => {
+					let Some(game) = commit(ctx, interaction, action.game).await? else { return Ok(()); };
+					respond_fire_report(ctx, interaction, game, report).await
+				}
+			}
 		}
 		
+		GameActionKind::Sonar | GameActionKind::Airstrike | GameActionKind::Cluster => {
+			let Some(weapon) = action.kind.weapon() else { return Ok(()); };
+
+			let board_size = action.game.rules().board_size;
+			let Some(Coord(pos)) = input_text(interaction, WEAPON_TEXT_ID)
+				.and_then(|t| Coord::parse(t.trim(), board_size)) else {
+				return respond_invalid_fire(ctx, interaction, action, InvalidFireReason::InvalidCoord).await;
+			};
+
+			let report = match action.game.use_weapon(weapon, pos) {
+				Ok(report) => report,
+				Err(err) => return respond_invalid_fire(ctx, interaction, action, InvalidFireReason::Rejected(err)).await
+			};
+			let Some(game) = commit(ctx, interaction, action.game).await? else { return Ok(()); };
+
+			match report {
+				WeaponReport::Sonar(report) => {
+					// Only the player who used the sonar gets to see what it found
+					let result = SonarResultRender(report.clone());
+					interaction.create_followup_message(ctx, |m| result.render_follow_up(m)).await?;
+
+					let state = SonarRender { state: game, report };
+					interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+					Ok(())
+				}
+				WeaponReport::Strike(report) => respond_fire_report(ctx, interaction, game, report).await
+			}
+		}
+
+		GameActionKind::DepthCharge => {
+			let board_size = action.game.rules().board_size;
+			let Some(Coord(pos)) = input_text(interaction, WEAPON_TEXT_ID)
+				.and_then(|t| Coord::parse(t.trim(), board_size)) else {
+				return respond_invalid_fire(ctx, interaction, action, InvalidFireReason::InvalidCoord).await;
+			};
+
+			match action.game.depth_charge(pos) {
+				Err(err) => respond_invalid_fire(ctx, interaction, action, InvalidFireReason::Rejected(err)).await,
+				Ok(report) => {
+					let Some(game) = commit(ctx, interaction, action.game).await? else { return Ok(()); };
+					respond_fire_report(ctx, interaction, game, report).await
+				}
+			}
+		}
+
+		GameActionKind::MoveShip => {
+			match move_ship(interaction, &mut action.game) {
+				Ok(report) => {
+					let Some(game) = commit(ctx, interaction, action.game).await? else { return Ok(()); };
+					let state = MoveRender { state: game, report };
+					interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+					Ok(())
+				}
+				Err(reason) => respond_invalid_fire(ctx, interaction, action, reason).await
+			}
+		}
+
+		GameActionKind::ArrangeShip => {
+			match arrange_ship(interaction, &mut action.game) {
+				Ok(()) => match save_game(ctx, action.game).await {
+					Ok(game) => render_modal_response(ctx, interaction, PlaceRender(game)).await,
+					Err(err) => render_modal_message(ctx, interaction, StaleGameRender(err)).await
+				}
+				Err(reason) => render_modal_response(ctx, interaction, InvalidPlaceRender(action.game, reason)).await
+			}
+		}
+
 		#[allow(unreachable_patterns)]
         _ => {
 			dbg!(action);
@@ -171,18 +338,177 @@ async fn handle_interaction_game_action(ctx: &Context, interaction: &ModalSubmit
     }
 }
 
+async fn render_modal_response(ctx: &Context, interaction: &ModalSubmitInteraction, state: impl InteractionRender) -> SerenityResult {
+	interaction.create_interaction_response(ctx, |f| f
+		.interaction_response_data(|d| state.render_interaction(d))
+		.kind(InteractionResponseType::UpdateMessage)
+	).await
+}
+
+// Answers with a new message instead, leaving the buttons as they are
+async fn render_modal_message(ctx: &Context, interaction: &ModalSubmitInteraction, state: impl InteractionRender) -> SerenityResult {
+	interaction.create_interaction_response(ctx, |f| f
+		.interaction_response_data(|d| state.render_interaction(d))
+		.kind(InteractionResponseType::ChannelMessageWithSource)
+	).await
+}
+
+// Remove the buttons; if needed, we'll add new ones
+async fn remove_modal_buttons(ctx: &Context, interaction: &ModalSubmitInteraction) -> SerenityResult {
+	interaction.create_interaction_response(ctx, |r| r
+		.interaction_response_data(|d| RemoveButtonsRender.render_interaction(d))
+		.kind(InteractionResponseType::UpdateMessage)
+	).await
+}
+
+// Stores the changed game before the buttons that changed it go away.
+// If it changed in the meantime, like from a second click, the player is told and nothing happens
+async fn commit(ctx: &Context, interaction: &ModalSubmitInteraction, game: Game) -> SerenityResult<Option<Game>> {
+	match save_game(ctx, game).await {
+		Ok(game) => {
+			remove_modal_buttons(ctx, interaction).await?;
+			Ok(Some(game))
+		}
+		Err(err) => {
+			render_modal_message(ctx, interaction, StaleGameRender(err)).await?;
+			Ok(None)
+		}
+	}
+}
+
+fn arrange_ship(interaction: &ModalSubmitInteraction, state: &mut GameState) -> std::result::Result<(), InvalidPlaceReason> {
+	let board_size = state.rules().board_size;
+
+	let index = input_text(interaction, ARRANGE_SHIP_TEXT_ID)
+		.and_then(|t| t.trim().parse::<usize>().ok())
+		.and_then(|n| n.checked_sub(1))
+		.ok_or(InvalidPlaceReason::InvalidShip)?;
+	let Coord(pos) = input_text(interaction, ARRANGE_TILE_TEXT_ID)
+		.and_then(|t| Coord::parse(t.trim(), board_size))
+		.ok_or(InvalidPlaceReason::InvalidCoord)?;
+	let Direction(rot) = input_text(interaction, ARRANGE_DIRECTION_TEXT_ID)
+		.and_then(Direction::parse)
+		.ok_or(InvalidPlaceReason::InvalidDirection)?;
+
+	// Traps are numbered after the ships, followed by the headquarters. Neither has a direction
+	let ship_count = state.current().ships().len();
+	let trap_count = state.current().traps().len();
+	if index == ship_count + trap_count {
+		return state
+			.place_headquarters(pos)
+			.map_err(InvalidPlaceReason::Placement);
+	}
+
+	if index >= ship_count {
+		return state
+			.place_trap(index - ship_count, pos)
+			.map_err(InvalidPlaceReason::Placement);
+	}
+
+	state
+		.place_ship(index, pos, rot)
+		.map_err(InvalidPlaceReason::Placement)
+}
+
+fn move_ship(interaction: &ModalSubmitInteraction, state: &mut GameState) -> std::result::Result<MoveReport, InvalidFireReason> {
+	let index = input_text(interaction, MOVE_SHIP_TEXT_ID)
+		.and_then(|t| t.trim().parse::<usize>().ok())
+		.and_then(|n| n.checked_sub(1))
+		.ok_or(InvalidFireReason::InvalidShip)?;
+	let Movement(ship_move) = input_text(interaction, MOVE_DIRECTION_TEXT_ID)
+		.and_then(Movement::parse)
+		.ok_or(InvalidFireReason::InvalidMove)?;
+
+	state
+		.move_ship(index, ship_move)
+		.map_err(InvalidFireReason::RejectedMove)
+}
+
+fn input_text<'a>(interaction: &'a ModalSubmitInteraction, custom_id: &str) -> Option<&'a str> {
+	interaction.data.components.iter()
+		.flat_map(|v| v.components.iter())
+		.find_map(|c| match c {
+			ActionRowComponent::InputText(t) if t.custom_id == custom_id => Some(t.value.as_str()),
+			_ => None
+		})
+}
+
+async fn respond_fire_report(ctx: &Context, interaction: &ModalSubmitInteraction, game: Game, report: FireReport) -> SerenityResult {
+	// The model already passed the turn if needed, so just send a message
+	let shoot_again = report.shoot_again;
+	let next = NextTurnRender {
+		state: game.clone(),
+		report
+	};
+
+	interaction.create_followup_message(ctx, |m| next.render_follow_up(m)).await?;
+
+	if shoot_again {
+		// Re-prompt the shooter with their updated boards
+		let state = FireRender(game);
+		interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+	}
+
+	Ok(())
+}
+
+async fn lock_in(ctx: &Context, interaction: &ModalSubmitInteraction, mut action: GameAction, coords: &[Vec2]) -> SerenityResult {
+	let report = match action.game.lock_in(coords) {
+		Ok(report) => report,
+		Err(err) => return respond_invalid_fire(ctx, interaction, action, InvalidFireReason::Rejected(err)).await
+	};
+	let Some(game) = commit(ctx, interaction, action.game).await? else { return Ok(()); };
+
+	match report {
+		None => {
+			let state = LockInRender(game);
+			interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+		}
+		Some(report) => {
+			let state = RoundRender { state: game, report };
+			interaction.create_followup_message(ctx, |m| state.render_follow_up(m)).await?;
+		}
+	}
+
+	Ok(())
+}
+
+// The game didn't change, so the new buttons work like the old ones
 async fn respond_invalid_fire(ctx: &Context, interaction: &ModalSubmitInteraction, action: GameAction, reason: InvalidFireReason) -> SerenityResult {
-    let state = InvalidFireRender(action.state, reason);
+	remove_modal_buttons(ctx, interaction).await?;
+
+    let state = InvalidFireRender(action.game, reason);
     interaction.create_followup_message(ctx, |f| state.render_follow_up(f)).await?;
 	Ok(())
 }
 
-fn check_players(player_1: &User, player_2: &User) -> std::result::Result<(), FailStartReason> {
-	if player_1.id == player_2.id {
-		Err(FailStartReason::Same)
-	} else {
-		check_player(player_1).and_then(|_| check_player(player_2))
+fn prepare_game(host: &User, opponents: &[&User], options: &str) -> std::result::Result<GameState, FailStartReason> {
+	let players: Vec<&User> = std::iter::once(host).chain(opponents.iter().copied()).collect();
+	let rules = parse_rules(options)?;
+	check_players(&players, rules.mode)?;
+
+	let player_ids: Vec<u64> = players.iter().map(|p| p.id.0).collect();
+	GameState::new(&player_ids, rules).ok_or(FailStartReason::PlayerCount)
+}
+
+fn check_players(players: &[&User], mode: GameMode) -> std::result::Result<(), FailStartReason> {
+	if players.len() < GameState::MIN_PLAYERS || players.len() > GameState::MAX_PLAYERS {
+		return Err(FailStartReason::PlayerCount);
+	}
+
+	if mode == GameMode::Teams && players.len() != GameState::TEAM_SIZE * 2 {
+		return Err(FailStartReason::TeamSize);
 	}
+
+	for (index, player) in players.iter().enumerate() {
+		if players[..index].iter().any(|p| p.id == player.id) {
+			return Err(FailStartReason::Same);
+		}
+
+		check_player(player)?;
+	}
+
+	Ok(())
 }
 
 fn check_player(user: &User) -> std::result::Result<(), FailStartReason> {
@@ -192,3 +518,102 @@ fn check_player(user: &User) -> std::result::Result<(), FailStartReason> {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use base64::{Engine as _, engine::general_purpose::STANDARD_NO_PAD};
+
+	use battleships_model::encode::{DecodeError, Encode};
+	use battleships_model::rules::GameRules;
+
+	use super::*;
+
+	// Starts a game between the given number of players, like a message mentioning all of them would
+	fn prepare(player_count: u64, options: &str) -> std::result::Result<GameState, FailStartReason> {
+		let users: Vec<User> = (1..=player_count)
+			.map(|id| {
+				let mut user = User::default();
+				user.id = UserId(id);
+				user.bot = false;
+				user
+			})
+			.collect();
+		let opponents: Vec<&User> = users[1..].iter().collect();
+		prepare_game(&users[0], &opponents, options)
+	}
+
+	// Starts a game on the default board with the options, which all four players have to fit on
+	fn start_at_ten_by_ten(options: &str) -> GameState {
+		let state = prepare(4, &format!("10x10 {options}")).unwrap_or_else(|reason| panic!("`{options}` failed to start: {reason:?}"));
+		assert_eq!(state.rules().board_size.get(), 10);
+		state
+	}
+
+	#[test]
+	fn four_players_start_at_ten_by_ten() {
+		let state = start_at_ten_by_ten("");
+		assert_eq!(state.players().len(), 4);
+
+		let store = GameStore::new();
+		let game = store.create(state);
+		let id = GameAction::new(GameActionKind::Place, game.clone()).to_id();
+		assert!(id.len() <= MAX_CUSTOM_ID_LEN);
+
+		let action = GameActionRef::from_id(&id).expect("the id parses again");
+		let GameRef::Stored(key) = action.game else { panic!("the id should refer to the stored game") };
+		assert_eq!(key, game.key);
+		assert_eq!(store.load(key).expect("the game is stored").encode(), game.encode());
+	}
+
+	#[test]
+	fn every_board_size_starts() {
+		let rules = GameRules::default();
+		let mut too_small = Vec::new();
+		for size in BoardSize::MIN..=BoardSize::MAX {
+			let fits = rules.fleet.fits_around(BoardSize::new(size).unwrap(), rules.placement, 0);
+			match prepare(4, &format!("{size}x{size}")) {
+				Ok(state) if fits => assert_eq!(state.rules().board_size.get(), size),
+				Err(FailStartReason::FleetTooLarge) if !fits => too_small.push(size),
+				res => panic!("a {size}x{size} game should {}start: {:?}", if fits { "" } else { "not " }, res.err())
+			}
+		}
+
+		// The classic fleet covers 17 tiles, which leaves enough room even on the smallest board
+		assert_eq!(too_small, Vec::<u8>::new());
+		assert!(matches!(prepare(2, &format!("{0}x{0}", BoardSize::MAX + 1)), Err(FailStartReason::BoardSize)));
+	}
+
+	#[test]
+	fn teams_start_at_ten_by_ten() {
+		let state = start_at_ten_by_ten("teams");
+		assert_eq!(state.players().len(), 2);
+
+		// The host plays with whoever was mentioned first
+		assert!(state.players()[0].is_member(1) && state.players()[0].is_member(2));
+		assert!(state.players()[1].is_member(3) && state.players()[1].is_member(4));
+	}
+
+	#[test]
+	fn every_fleet_starts_at_ten_by_ten() {
+		for fleet in ["classic", "russian", "duel", "shapes"] {
+			start_at_ten_by_ten(fleet);
+		}
+	}
+
+	#[test]
+	fn traps_start_at_ten_by_ten() {
+		let state = start_at_ten_by_ten("mines");
+		assert!(!state.rules().traps.layout().is_empty());
+	}
+
+	#[test]
+	fn only_old_ids_carry_a_state() {
+		// Anyone could make up a button with a state of their own, so only ids from before the store are taken in
+		let state = prepare(2, "").expect("two players start a classic game");
+		let mut id = CUSTOM_ID_PREFIX.to_owned();
+		id.push(GameActionKind::Place.to_char());
+		STANDARD_NO_PAD.encode_string(state.encode(), &mut id);
+
+		assert!(matches!(GameActionRef::from_id(&id), Err(GameActionParseError::InvalidData(DecodeError::UnknownVersion))));
+	}
+}
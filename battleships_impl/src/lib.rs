@@ -1,4 +1,6 @@
 pub mod hooks;
 pub mod render;
 pub mod action;
+pub mod options;
+pub mod store;
 mod consts;
\ No newline at end of file
@@ -0,0 +1,165 @@
+use battleships_model::fleet::Fleet;
+use battleships_model::game_state::BoardSize;
+use battleships_model::rules::{ArmorRule, GameMode, GameRules, LayerRule, MovementRule, PlacementRule, ShotRule, TerrainRule, TopologyRule, TrapRule, TurnRule, VictoryRule, WeaponRule};
+use battleships_model::terrain::TerrainPreset;
+use rand::{thread_rng, Rng};
+use serenity::model::user::User;
+
+use crate::render::FailStartReason;
+
+/// The mentioned users in the order they were written, which decides the teams.
+/// Discord lists mentions in no particular order. Users that aren't written out, like the author of a reply, come last.
+pub fn order_mentions<'a>(content: &str, mentions: &'a [User]) -> Vec<&'a User> {
+	let mut ids = Vec::new();
+	for part in content.split("<@").skip(1) {
+		let part = part.strip_prefix('!').unwrap_or(part);
+		let Some((id, _)) = part.split_once('>') else { continue; };
+		if let Ok(id) = id.parse::<u64>() { ids.push(id); }
+	}
+
+	let mut res: Vec<&User> = mentions.iter().collect();
+	res.sort_by_key(|u| ids.iter().position(|&id| id == u.id.0).unwrap_or(usize::MAX));
+	res
+}
+
+/// Parses the rules out of the message that started the game.
+/// Words that aren't options are ignored, so mentions and chatter can stay in.
+pub fn parse_rules(content: &str) -> Result<GameRules, FailStartReason> {
+	let mut res = GameRules::default();
+
+	for word in content.split_whitespace() {
+		if let Some(size) = parse_board_size(word) {
+			res.board_size = size?;
+		} else if let Some(fleet) = parse_fleet(word) {
+			res.fleet = fleet;
+		} else if let Some(terrain) = parse_terrain(word) {
+			res.terrain = terrain;
+		} else if let Some(victory) = parse_victory(word) {
+			res.victory = victory?;
+		} else if let Some(placement) = parse_placement(word) {
+			res.placement = placement;
+		} else if word.eq_ignore_ascii_case("salvo") {
+			res.shots = ShotRule::Salvo;
+		} else if word.eq_ignore_ascii_case("again") {
+			res.turns = TurnRule::ShootAgainOnHit;
+		} else if word.eq_ignore_ascii_case("simultaneous") {
+			res.turns = TurnRule::Simultaneous;
+		} else if word.eq_ignore_ascii_case("teams") {
+			res.mode = GameMode::Teams;
+		} else if word.eq_ignore_ascii_case("arsenal") {
+			res.weapons = WeaponRule::Arsenal;
+		} else if word.eq_ignore_ascii_case("mines") {
+			res.traps = TrapRule::MinesAndDecoys;
+		} else if word.eq_ignore_ascii_case("moving") {
+			res.movement = MovementRule::Moving;
+		} else if word.eq_ignore_ascii_case("armor") {
+			res.armor = ArmorRule::Armored;
+		} else if word.eq_ignore_ascii_case("wrap") {
+			res.topology = TopologyRule::Wrapping;
+		} else if word.eq_ignore_ascii_case("hex") {
+			res.topology = TopologyRule::Hex;
+		} else if word.eq_ignore_ascii_case("dive") {
+			res.layers = LayerRule::Submerged;
+		}
+	}
+
+	if res.topology == TopologyRule::Hex && !res.fleet.ships().iter().all(|s| s.is_straight()) {
+		return Err(FailStartReason::HexShapes);
+	}
+
+	// Those take up a whole turn, which doesn't work when everyone fires at once
+	let whole_turn = res.weapons == WeaponRule::Arsenal || res.movement == MovementRule::Moving || res.layers == LayerRule::Submerged;
+	if res.turns == TurnRule::Simultaneous && whole_turn {
+		return Err(FailStartReason::Simultaneous);
+	}
+
+	let headquarters = usize::from(res.victory == VictoryRule::Headquarters);
+	let obstacles = res.traps.layout().len() + res.terrain.layout(res.board_size).tile_count() + headquarters;
+	if !res.fleet.fits_around(res.board_size, res.placement, obstacles) {
+		return Err(FailStartReason::FleetTooLarge);
+	}
+
+	Ok(res)
+}
+
+// Board sizes are written as "8x8", "12x12" and so on.
+fn parse_board_size(word: &str) -> Option<Result<BoardSize, FailStartReason>> {
+	let (width, height) = word.split_once(['x', 'X'])?;
+	let width = width.parse::<u8>().ok()?;
+	let height = height.parse::<u8>().ok()?;
+
+	if width != height {
+		return Some(Err(FailStartReason::BoardSize));
+	}
+
+	Some(BoardSize::new(width).ok_or(FailStartReason::BoardSize))
+}
+
+fn parse_fleet(word: &str) -> Option<Fleet> {
+	match word.to_ascii_lowercase().as_str() {
+		"classic" => Some(Fleet::classic()),
+		"russian" => Some(Fleet::russian()),
+		"duel" => Some(Fleet::duel()),
+		"shapes" => Some(Fleet::shapes()),
+		_ => None
+	}
+}
+
+// Either a preset, or "islands" with an optional seed like "islands:1234"
+fn parse_terrain(word: &str) -> Option<TerrainRule> {
+	let word = word.to_ascii_lowercase();
+	if let Some(preset) = TerrainPreset::ALL.into_iter().find(|p| p.label() == word) {
+		return Some(TerrainRule::Preset(preset));
+	}
+
+	match word.split_once(':') {
+		Some(("islands", seed)) => seed.parse().ok().map(TerrainRule::Seeded),
+		None if word == "islands" => Some(TerrainRule::Seeded(thread_rng().gen())),
+		_ => None
+	}
+}
+
+// A points race may be given its length, like "points:20"
+fn parse_victory(word: &str) -> Option<Result<VictoryRule, FailStartReason>> {
+	let word = word.to_ascii_lowercase();
+	match word.split_once(':') {
+		Some(("points", rounds)) => {
+			let rounds = rounds.parse::<u8>().ok().filter(|r| (1..=VictoryRule::MAX_ROUNDS).contains(r));
+			Some(rounds.map(|rounds| VictoryRule::Points { rounds }).ok_or(FailStartReason::Rounds))
+		}
+		None if word == "points" => Some(Ok(VictoryRule::Points { rounds: VictoryRule::DEFAULT_ROUNDS })),
+		None if word == "flagship" => Some(Ok(VictoryRule::Flagship)),
+		None if word == "hq" => Some(Ok(VictoryRule::Headquarters)),
+		_ => None
+	}
+}
+
+fn parse_placement(word: &str) -> Option<PlacementRule> {
+	match word.to_ascii_lowercase().as_str() {
+		"notouch" => Some(PlacementRule::NoTouching { mark_surroundings: false }),
+		"notouch+mark" => Some(PlacementRule::NoTouching { mark_surroundings: true }),
+		_ => None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serenity::model::id::UserId;
+
+	use super::*;
+
+	fn user(id: u64) -> User {
+		let mut user = User::default();
+		user.id = UserId(id);
+		user
+	}
+
+	#[test]
+	fn mentions_keep_the_written_order() {
+		let mentions = [user(4), user(9), user(2), user(7)];
+		let content = "<@9> teams <@!2> vs <@4> and <@&5>";
+
+		let order: Vec<u64> = order_mentions(content, &mentions).iter().map(|u| u.id.0).collect();
+		assert_eq!(order, [9, 2, 4, 7]);
+	}
+}
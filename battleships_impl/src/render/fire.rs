@@ -1,70 +1,202 @@
-use serenity::builder::{CreateInteractionResponseData, CreateInteractionResponseFollowup};
-use serenity::model::prelude::*;
+use serenity::builder::{CreateComponents, CreateInteractionResponseData, CreateInteractionResponseFollowup};
 use serenity::model::prelude::component::{ButtonStyle, InputTextStyle};
+use serenity::utils::MessageBuilder;
 
 use battleships_model::game_state::*;
+use battleships_model::movement::MoveError;
+use battleships_model::rules::{LayerRule, MovementRule, TurnRule, WeaponRule};
+use battleships_model::shot::FireError;
+use battleships_model::weapon::Weapon;
 use crate::consts::{EMBED_COLOR, RETRY_COLOR};
-use crate::action::{GameAction, GameActionKind};
+use crate::action::{GameAction, GameActionKind, Coord, Direction};
+use crate::store::Game;
 
 use super::{FollowUpRender, InteractionRender};
+use super::utility::mention::mention_members;
 use super::utility::render_target::*;
 
 #[derive(Clone)]
-pub struct FireRender(pub GameState);
+pub struct FireRender(pub Game);
 impl FollowUpRender for FireRender {
 	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
 		let (curr_turn, other_turn) = self.0.turns();
+		let rules = self.0.rules();
 
-		let mut buffer = RenderTarget::new();
-		buffer.set_all_fields(&other_turn);
-		
 		msg
 		.embed(|e| e
 			.description(
-				buffer.render_grid(
-					&format!("**[Enemy]** {}", UserId(other_turn.user_id).mention()),
+				render_layers(
+					rules,
+					other_turn,
+					&format!("**[Enemy]** {}", mention_members(other_turn)),
 					&Emotes::ENEMY))
 			.colour(EMBED_COLOR));
 
-		buffer = RenderTarget::new();
-		buffer.set_all_fields(&curr_turn);
+		let mut desc = render_layers(rules, curr_turn, &format!("**[Own]** {}", mention_members(curr_turn)), &Emotes::OWN);
+
+		// Only ships that weren't hit yet may move, numbered like during placement
+		if self.0.rules().movement == MovementRule::Moving {
+			for (index, ship) in curr_turn.ships().iter().enumerate().filter(|(_, s)| curr_turn.is_intact(s)) {
+				let pos = Coord(ship.state.position());
+				let dir = Direction(ship.state.rotation());
+				desc.push_str(&format!("\n**{}.** {} at *{pos}* ({dir})", index + 1, ship.info.label));
+			}
+		}
+
+		if let Some(flagship) = curr_turn.flagship() {
+			desc.push_str(&format!("\n\u{1F451} Your flagship is the *{}*.", flagship.info.label));
+		}
+
+		if let Some(rounds) = self.0.rounds_left() {
+			desc.push_str(&format!("\n**{}** points, **{rounds}** rounds left.", curr_turn.points()));
+		}
 
 		msg
 		.embed(|e| e
-			.description(
-				buffer.render_grid(
-					&format!("**[Own]** {}", UserId(curr_turn.user_id).mention()),
-					&Emotes::OWN))
+			.description(desc)
 			.colour(EMBED_COLOR));
 
-		let button_id = GameAction::new(GameActionKind::Fire, self.0).to_id();
+		// With more than one opponent left, the player may switch targets
+		if self.0.opponents().nth(1).is_some() {
+			let mut desc = MessageBuilder::new();
+			desc.push_bold_line("[Opponents]");
+
+			for turn in self.0.opponents() {
+				let player = self.0.player(turn);
+				let ships_left = player.ships().iter().filter(|s| !player.is_sunk(s)).count();
+
+				desc.push_bold(format!("{}. ", turn.num()))
+					.push(mention_members(player))
+					.push(format!(" ({ships_left} ships left)"));
+				if turn == self.0.target_turn() { desc.push(" \u{1F3AF}"); }
+				desc.push('\n');
+			}
+
+			msg
+			.embed(|e| e
+				.description(desc)
+				.colour(EMBED_COLOR));
+		}
 
 		msg
 		.ephemeral(true)
-		.components(|c| c
-			.create_action_row(|r| r
-				.create_button(|b| b
-					.custom_id(button_id)
-					.label("Fire")
-					.style(ButtonStyle::Primary))))
+		.components(|c| create_fire_components(self.0, c))
+	}
+}
+
+// The fire button, followed by buttons to switch targets and to use special weapons
+fn create_fire_components(state: Game, components: &mut CreateComponents) -> &mut CreateComponents {
+	let target = state.target_turn();
+	let target_buttons: Vec<(Turn, String)> = if state.opponents().nth(1).is_some() {
+		state.opponents()
+			.map(|turn| {
+				let mut state = state.clone();
+				state.set_target(turn);
+				(turn, GameAction::new(GameActionKind::ChooseTarget, state).to_id())
+			})
+			.collect()
+	} else {
+		Vec::new()
+	};
+
+	let mut weapon_buttons: Vec<(String, bool, String)> = if state.rules().weapons == WeaponRule::Arsenal {
+		Weapon::ALL.into_iter()
+			.map(|weapon| {
+				let charges = state.current().charges(weapon);
+				let id = GameAction::new(GameActionKind::from_weapon(weapon), state.clone()).to_id();
+				(format!("{} ({charges})", weapon.label()), charges > 0, id)
+			})
+			.collect()
+	} else {
+		Vec::new()
+	};
+
+	// Depth charges never run out
+	if state.rules().layers == LayerRule::Submerged {
+		let id = GameAction::new(GameActionKind::DepthCharge, state.clone()).to_id();
+		weapon_buttons.push(("Depth Charge".to_owned(), true, id));
+	}
+
+	let move_id = (state.rules().movement == MovementRule::Moving)
+		.then(|| GameAction::new(GameActionKind::MoveShip, state.clone()).to_id());
+
+	let label = fire_label(&state);
+	let button_id = GameAction::new(GameActionKind::Fire, state).to_id();
+
+	components
+	.create_action_row(|r| {
+		r.create_button(|b| b
+			.custom_id(button_id)
+			.label(label)
+			.style(ButtonStyle::Primary));
+
+		for (turn, id) in target_buttons {
+			r.create_button(|b| b
+				.custom_id(id)
+				.label(format!("Target {}", turn.num()))
+				.style(ButtonStyle::Secondary)
+				.disabled(turn == target));
+		}
+
+		if let Some(id) = move_id {
+			r.create_button(|b| b
+				.custom_id(id)
+				.label("Move Ship")
+				.style(ButtonStyle::Secondary));
+		}
+
+		r
+	});
+
+	if !weapon_buttons.is_empty() {
+		components
+		.create_action_row(|r| {
+			for (label, enabled, id) in weapon_buttons {
+				r.create_button(|b| b
+					.custom_id(id)
+					.label(label)
+					.style(ButtonStyle::Danger)
+					.disabled(!enabled));
+			}
+
+			r
+		});
+	}
+
+	components
+}
+
+fn fire_label(state: &GameState) -> String {
+	let verb = match state.rules().turns {
+		TurnRule::Simultaneous => "Lock In",
+		_ => "Fire"
+	};
+
+	match state.shots_per_turn() {
+		1 => verb.to_owned(),
+		shots => format!("{verb} ({shots} shots)")
 	}
 }
 
 #[derive(Copy, Clone)]
 pub enum InvalidFireReason {
 	InvalidCoord,
-	AlreadyHit
+	InvalidShip,
+	InvalidMove,
+	Rejected(FireError),
+	RejectedMove(MoveError)
 }
 
 #[derive(Clone)]
-pub struct InvalidFireRender(pub GameState, pub InvalidFireReason);
+pub struct InvalidFireRender(pub Game, pub InvalidFireReason);
 impl FollowUpRender for InvalidFireRender {
 	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
-		let button_id = GameAction::new(GameActionKind::Fire, self.0).to_id();
-
 		let reason = match self.1 {
-			InvalidFireReason::InvalidCoord => "**[**That coordinate is invalid.**]**",
-			InvalidFireReason::AlreadyHit => "**[**You already fired at that coordinate.**]**",
+			InvalidFireReason::InvalidCoord => "**[**That coordinate is invalid.**]**".to_owned(),
+			InvalidFireReason::InvalidShip => "**[**There is no ship with that number.**]**".to_owned(),
+			InvalidFireReason::InvalidMove => "**[**The move has to be U, D, L, R or T.**]**".to_owned(),
+			InvalidFireReason::Rejected(err) => format!("**[**{err}**]**"),
+			InvalidFireReason::RejectedMove(err) => format!("**[**{err}**]**")
 		};
 
 		msg
@@ -72,35 +204,123 @@ impl FollowUpRender for InvalidFireRender {
 		.embed(|e| e
 			.description(reason)
 			.colour(RETRY_COLOR))
-		.components(|c| c
-			.create_action_row(|r| r
-				.create_button(|b| b
-					.custom_id(button_id)
-					.label("Fire")
-					.style(ButtonStyle::Primary))))
+		.components(|c| create_fire_components(self.0, c))
 	}
 }
 
 const FIRE_TEXT_ID: &str = "fire";
 
 #[derive(Clone)]
-pub struct ChooseFireRender(pub GameState);
+pub struct ChooseFireRender(pub Game);
 impl InteractionRender for ChooseFireRender {
 	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let shots = self.0.shots_per_turn();
 		let custom_id = GameAction::new(GameActionKind::Fire, self.0).to_id();
 
+		// Each tile takes at most 3 characters and a separator
+		let (title, label, placeholder) = match shots {
+			1 => ("Enter Target", "Tile".to_owned(), "f.e. B4"),
+			_ => ("Enter Targets", format!("{shots} Tiles"), "f.e. B4 C5 D6")
+		};
+
 		msg
 		.custom_id(custom_id)
-		.title("Enter Target")
+		.title(title)
 		.components(|c| c
 			.create_action_row(|r| r
 				.create_input_text(|i| i
 					.custom_id(FIRE_TEXT_ID)
-					.label("Tile")
+					.label(label)
+					.placeholder(placeholder)
+					.min_length(2)
+					.max_length((shots * 4 - 1) as u64)
+					.style(InputTextStyle::Short)
+					.required(true))))
+	}
+}
+pub(crate) const WEAPON_TEXT_ID: &str = "weapon";
+
+#[derive(Clone)]
+pub struct ChooseWeaponRender(pub Game, pub Weapon);
+impl InteractionRender for ChooseWeaponRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let custom_id = GameAction::new(GameActionKind::from_weapon(self.1), self.0).to_id();
+
+		let label = match self.1 {
+			Weapon::Sonar => "Center of the 3x3 Area",
+			Weapon::Airstrike => "Leftmost Tile of the Row",
+			Weapon::Cluster => "Center Tile"
+		};
+
+		msg
+		.custom_id(custom_id)
+		.title(self.1.label())
+		.components(|c| c
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(WEAPON_TEXT_ID)
+					.label(label)
 					.placeholder("f.e. B4")
 					.min_length(2)
 					.max_length(3)
 					.style(InputTextStyle::Short)
 					.required(true))))
 	}
-}
\ No newline at end of file
+}
+
+#[derive(Clone)]
+pub struct ChooseDepthChargeRender(pub Game);
+impl InteractionRender for ChooseDepthChargeRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let custom_id = GameAction::new(GameActionKind::DepthCharge, self.0).to_id();
+
+		msg
+		.custom_id(custom_id)
+		.title("Depth Charge")
+		.components(|c| c
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(WEAPON_TEXT_ID)
+					.label("Center Tile")
+					.placeholder("f.e. B4")
+					.min_length(2)
+					.max_length(3)
+					.style(InputTextStyle::Short)
+					.required(true))))
+	}
+}
+
+pub(crate) const MOVE_SHIP_TEXT_ID: &str = "ship";
+pub(crate) const MOVE_DIRECTION_TEXT_ID: &str = "move";
+
+#[derive(Clone)]
+pub struct ChooseMoveRender(pub Game);
+impl InteractionRender for ChooseMoveRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let ship_count = self.0.current().ships().len();
+		let custom_id = GameAction::new(GameActionKind::MoveShip, self.0).to_id();
+
+		msg
+		.custom_id(custom_id)
+		.title("Move Ship")
+		.components(|c| c
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(MOVE_SHIP_TEXT_ID)
+					.label(format!("Ship (1-{ship_count})"))
+					.placeholder("f.e. 1")
+					.min_length(1)
+					.max_length(2)
+					.style(InputTextStyle::Short)
+					.required(true)))
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(MOVE_DIRECTION_TEXT_ID)
+					.label("Up, Down, Left, Right or Turn (U/D/L/R/T)")
+					.placeholder("f.e. U")
+					.min_length(1)
+					.max_length(5)
+					.style(InputTextStyle::Short)
+					.required(true))))
+	}
+}
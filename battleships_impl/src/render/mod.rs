@@ -10,10 +10,12 @@ pub(crate) mod utility;
 
 // Selectively re-export the sub-modules
 pub use start::{StartRender, FailStartRender, FailStartReason};
-pub use place::{NextPlaceRender, PlaceRender};
-pub use next_turns::{FirstTurnRender, NextTurnRender, NextTurnInfo};
-pub use fire::{FireRender, ChooseFireRender, InvalidFireRender, InvalidFireReason};
-pub use wrong::{NotYourTurnRender, NotInvolvedRender};
+pub use place::{NextPlaceRender, PlaceRender, ChooseArrangeRender, InvalidPlaceRender, InvalidPlaceReason};
+pub(crate) use place::{ARRANGE_SHIP_TEXT_ID, ARRANGE_TILE_TEXT_ID, ARRANGE_DIRECTION_TEXT_ID};
+pub use next_turns::{FirstTurnRender, NextTurnRender, SonarRender, SonarResultRender, MoveRender, LockInRender, RoundRender};
+pub use fire::{FireRender, ChooseFireRender, ChooseWeaponRender, ChooseDepthChargeRender, ChooseMoveRender, InvalidFireRender, InvalidFireReason};
+pub(crate) use fire::{WEAPON_TEXT_ID, MOVE_SHIP_TEXT_ID, MOVE_DIRECTION_TEXT_ID};
+pub use wrong::{NotYourTurnRender, NotInvolvedRender, WrongPhaseRender, StaleGameRender};
 pub use utility::renders::RemoveButtonsRender;
 
 pub trait InteractionRender {
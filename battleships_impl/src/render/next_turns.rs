@@ -1,23 +1,31 @@
 use serenity::builder::{CreateInteractionResponseFollowup};
 use serenity::model::prelude::*;
 use serenity::utils::*;
+use battleships_model::depth::Layer;
 use battleships_model::game_state::*;
+use battleships_model::movement::MoveReport;
+use battleships_model::round::RoundReport;
+use battleships_model::shot::{FireReport, ShotResult};
+use battleships_model::victory::{Defeat, GameEnd};
+use battleships_model::weapon::{SonarReport, Weapon};
 
 use crate::consts::{EMBED_COLOR, RETRY_COLOR};
 use crate::action::{GameActionKind, Coord};
+use crate::store::Game;
 
 use super::FollowUpRender;
+use super::utility::mention::mention_members;
 use super::utility::render_target::*;
 use super::utility::renders::SharedPrepareRender;
 
 #[derive(Clone)]
-pub struct FirstTurnRender(pub GameState);
+pub struct FirstTurnRender(pub Game);
 impl FollowUpRender for FirstTurnRender {
 	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
 		let mut desc = MessageBuilder::new();
 
 		desc.push_bold('[')
-			.mention(&UserId(self.0.current().user_id))
+			.mention(&UserId(self.0.current().shooter_id()))
 			.push(", it's your turn!")
 			.push_bold(']');
 	
@@ -31,46 +39,297 @@ impl FollowUpRender for FirstTurnRender {
 }
 
 #[derive(Clone)]
-pub enum NextTurnInfo {
-	Miss,
-	Hit,
-	Sunk { kind: &'static str, loss: bool }
+pub struct NextTurnRender { pub state: Game, pub report: FireReport }
+impl FollowUpRender for NextTurnRender {
+	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+		let mut desc = MessageBuilder::new();
+		render_report(&self.state, &self.report, &mut desc);
+
+		if self.report.game_over {
+			// Usually the target lost, but a mine may have finished off the shooter as well
+			let losers: Vec<Turn> = [self.report.target, self.report.shooter].into_iter()
+				.filter(|&t| self.state.player(t).is_defeated())
+				.collect();
+			render_end_of_game(&self.state, &losers, &mut desc, msg);
+		} else if self.report.shoot_again {
+			// The shooter gets a new fire prompt separately, so no buttons here
+			desc.push_bold('[')
+				.mention(&UserId(self.report.shooter_id))
+				.push(", you may fire again!")
+				.push_bold(']');
+		} else {
+			desc.push_bold('[')
+				.mention(&UserId(self.state.current().shooter_id()))
+				.push(", it's your turn!")
+				.push_bold(']');
+	
+			SharedPrepareRender(self.state, GameActionKind::StartTurn)
+			.render_follow_up(msg);
+		}
+
+		msg
+		.ephemeral(false)
+		.embed(|e| e
+			.description(desc)
+			.color(EMBED_COLOR))
+	}
+}
+
+fn is_exposed(player: &PlayerState, tile: Vec2) -> bool {
+	player.trap_at(tile).is_some_and(|t| t.exposed)
+}
+
+fn push_exposed_decoy(desc: &mut MessageBuilder, tile: Vec2) {
+	desc.push_bold('[')
+		.push("A DECOY was exposed at ")
+		.push_italic(Coord(tile))
+		.push('!')
+		.push_bold(']')
+		.push('\n');
+}
+
+// Describes one player's shots, along with everything they set off
+fn render_report(state: &GameState, report: &FireReport, desc: &mut MessageBuilder) {
+	// The turn has already passed on, so the report says who fired at whom.
+	let target = state.player(report.target);
+
+	desc.push_bold('[')
+		.mention(&UserId(report.shooter_id));
+
+	if let (None, Layer::Surface, [(tile, result)]) = (report.weapon, report.layer, &report.shots[..]) {
+		desc.push(" fired at ");
+		if state.players().len() > 2 {
+			desc.push(mention_members(target)).push("'s ");
+		}
+
+		desc.push_italic(Coord(*tile))
+			.push(". ");
+
+		let terrain = state.rules().terrain.layout(state.rules().board_size);
+		match result {
+			ShotResult::Miss => match terrain.kind_at(*tile) {
+				Some(kind) => desc.push("It hit a ").push(kind.label().to_uppercase()).push('!'),
+				None => desc.push("It MISSED!")
+			},
+			ShotResult::Hit => desc.push("It HIT!"),
+			ShotResult::Sunk(info) => desc.push("It HIT and a ").push_italic(info.label).push(" was SUNK!"),
+			ShotResult::Damaged => desc.push("It HIT, but the armor held!"),
+			ShotResult::Mine => desc.push("It hit a MINE!"),
+			// Decoys pass for hits until they are exposed
+			ShotResult::Decoy if is_exposed(target, *tile) => desc.push("It hit a DECOY!"),
+			ShotResult::Decoy => desc.push("It HIT!"),
+			ShotResult::Captured => desc.push("It CAPTURED the HEADQUARTERS!")
+		};
+	} else {
+		render_salvo(state, report, desc);
+	}
+
+	desc.push_bold(']')
+		.push('\n');
+
+	render_traps(report, desc);
+
+	if report.defeated && !report.game_over {
+		desc.push_bold('[');
+		push_defeat(desc, target);
+		desc.push(" and is out of the game!")
+			.push_bold(']')
+			.push('\n');
+	}
+
+	let shooter = state.player(report.shooter);
+	if shooter.is_defeated() && !report.game_over && !report.backfire.is_empty() {
+		desc.push_bold('[');
+		push_defeat(desc, shooter);
+		desc.push(" to a mine and is out of the game!")
+			.push_bold(']')
+			.push('\n');
+	}
+}
+
+// Says how the player lost, without any punctuation
+fn push_defeat(desc: &mut MessageBuilder, player: &PlayerState) {
+	desc.push(mention_members(player));
+	match player.defeat() {
+		Some(Defeat::Flagship(info)) => desc.push(" lost their flagship, the ").push_italic(info.label),
+		Some(Defeat::Headquarters(pos)) => desc.push(" lost their headquarters at ").push_italic(Coord(pos)),
+		Some(Defeat::Fleet) | None => desc.push(" lost all their ships")
+	};
+}
+
+fn render_salvo(state: &GameState, report: &FireReport, desc: &mut MessageBuilder) {
+	desc.push(match report.weapon {
+		None if report.layer == Layer::Depth => " dropped a depth charge on ",
+		None | Some(Weapon::Sonar) => " fired a salvo at ",
+		Some(Weapon::Airstrike) => " called in an airstrike on ",
+		Some(Weapon::Cluster) => " fired a cluster shot at "
+	});
+	if state.players().len() > 2 {
+		let target = state.player(report.target);
+		desc.push(mention_members(target)).push("'s ");
+	}
+
+	for (index, &(tile, _)) in report.shots.iter().enumerate() {
+		if index > 0 { desc.push(", "); }
+		desc.push_italic(Coord(tile));
+	}
+
+	let hits = report.hit_count();
+	let misses = report.shots.len() - hits;
+	desc.push(format!(". {hits} HIT and {misses} MISSED!"));
+
+	for info in report.sunk() {
+		desc.push(" A ").push_italic(info.label).push(" was SUNK!");
+	}
+
+	let damaged = report.shots.iter().filter(|(_, r)| *r == ShotResult::Damaged).count();
+	if damaged > 0 {
+		desc.push(format!(" The armor held on {damaged} of them!"));
+	}
+
+	for _ in report.shots.iter().filter(|(_, r)| *r == ShotResult::Mine) {
+		desc.push(" A MINE went off!");
+	}
+
+	if report.shots.iter().any(|(_, r)| *r == ShotResult::Captured) {
+		desc.push(" The HEADQUARTERS were CAPTURED!");
+	}
 }
 
+// Mines hurt the shooter's own fleet, and decoys may have been given away
+fn render_traps(report: &FireReport, desc: &mut MessageBuilder) {
+	for &(tile, result) in &report.backfire {
+		desc.push_bold('[')
+			.mention(&UserId(report.shooter_id))
+			.push("'s fleet was hit at ")
+			.push_italic(Coord(tile));
+
+		match result {
+			ShotResult::Sunk(info) => { desc.push(" and a ").push_italic(info.label).push(" was SUNK"); }
+			ShotResult::Damaged => { desc.push(", but the armor held,"); }
+			_ => {}
+		}
+
+		desc.push(" by the blast!")
+			.push_bold(']')
+			.push('\n');
+	}
+
+	for &tile in &report.exposed {
+		push_exposed_decoy(desc, tile);
+	}
+}
+
+// Explains why the game ended, announces the winner and reveals every board.
+// The losers are the players who dropped out with the last turn.
+fn render_end_of_game(state: &GameState, losers: &[Turn], desc: &mut MessageBuilder, msg: &mut CreateInteractionResponseFollowup) {
+	for &turn in losers {
+		desc.push_bold('[');
+		push_defeat(desc, state.player(turn));
+		desc.push('!')
+			.push_bold(']')
+			.push('\n');
+	}
+
+	let winner = state.winner();
+	desc.push_bold('[');
+	if state.end() == Some(GameEnd::RoundLimit) {
+		desc.push("The last round is over! ");
+		for (index, player) in state.players().iter().enumerate() {
+			if index > 0 { desc.push(", "); }
+			desc.push(mention_members(player))
+				.push(format!(" scored {}", player.points()));
+		}
+		desc.push(". ");
+	}
+
+	match winner {
+		Some(winner) => desc.push(mention_members(winner)).push(" wins!"),
+		None if state.end() == Some(GameEnd::RoundLimit) => desc.push("The points are tied. It's a DRAW!"),
+		None => desc.push("Nobody is left standing. It's a DRAW!")
+	};
+	desc.push_bold(']');
+
+	// The winner comes last
+	let is_winner = |player: &PlayerState| winner.is_some_and(|w| w.user_id == player.user_id);
+	let boards = state.players().iter().filter(|p| !is_winner(p)).chain(winner);
+	for player in boards {
+		let (label, color) = match winner {
+			Some(_) if is_winner(player) => ("Winner", EMBED_COLOR),
+			Some(_) => ("Loser", RETRY_COLOR),
+			None => ("Draw", RETRY_COLOR)
+		};
+
+		msg.embed(|e| e
+			.description(
+				render_layers(
+					state.rules(),
+					player,
+					&format!("**[{label}]** {}", mention_members(player)),
+					&Emotes::OWN))
+			.color(color));
+	}
+}
+
+/// Tells everyone that a player locked in their shots, but not where they aim.
 #[derive(Clone)]
-pub struct NextTurnRender { pub state: GameState, pub tile: Coord, pub info: NextTurnInfo }
-impl FollowUpRender for NextTurnRender {
+pub struct LockInRender(pub Game);
+impl FollowUpRender for LockInRender {
 	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
-		// Current turn is the one that was shot *AT*
-		// Target turn was the shooter
-		// So if it's Sunk with loss=true, the current player lost.
+		let locked = self.0.players().iter().filter(|p| p.pending().is_some()).count();
+		let alive = self.0.players().iter().filter(|p| !p.is_defeated()).count();
 
 		let mut desc = MessageBuilder::new();
 		desc.push_bold('[')
-			.mention(&UserId(self.state.target().user_id))
-			.push(" fired at ")
-			.push_italic(self.tile.to_string())
-			.push(". ");
+			.push(format!("{locked} of {alive} players locked in their shots."))
+			.push_bold(']')
+			.push('\n');
 
-		match self.info {
-			NextTurnInfo::Miss => desc.push("It MISSED!"),
-			NextTurnInfo::Hit => desc.push("It HIT!"),
-			NextTurnInfo::Sunk { kind, .. } => desc.push("It HIT and a ").push_italic(kind).push(" was SUNK!")
-		};
+		desc.push_bold('[')
+			.mention(&UserId(self.0.current().shooter_id()))
+			.push(", lock in your shots!")
+			.push_bold(']');
 
-		desc.push_bold(']')
-			.push('\n');
+		SharedPrepareRender(self.0, GameActionKind::StartTurn)
+		.render_follow_up(msg)
+		.ephemeral(false)
+		.embed(|e| e
+			.description(desc)
+			.color(EMBED_COLOR))
+	}
+}
+
+/// Sums up a round in which every player's shots landed at once.
+#[derive(Clone)]
+pub struct RoundRender { pub state: Game, pub report: RoundReport }
+impl FollowUpRender for RoundRender {
+	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+		let mut desc = MessageBuilder::new();
+		desc.push_bold_line("[All shots are in!]");
+
+		for report in &self.report.reports {
+			render_report(&self.state, report, &mut desc);
+		}
+
+		if !self.report.game_over {
+			for &turn in &self.report.defeated {
+				desc.push_bold('[');
+				push_defeat(&mut desc, self.state.player(turn));
+				desc.push(" and is out of the game!")
+					.push_bold(']')
+					.push('\n');
+			}
 
-		if matches!(self.info, NextTurnInfo::Sunk { loss: true, .. }) {
-			self.render_end_of_game(&mut desc, msg);
-		} else {
 			desc.push_bold('[')
-				.mention(&UserId(self.state.current().user_id))
-				.push(", it's your turn!")
+				.mention(&UserId(self.state.current().shooter_id()))
+				.push(", lock in your shots for the next round!")
 				.push_bold(']');
-	
+
 			SharedPrepareRender(self.state, GameActionKind::StartTurn)
 			.render_follow_up(msg);
+		} else {
+			// Every fleet that was left may have gone down in the same round
+			render_end_of_game(&self.state, &self.report.defeated, &mut desc, msg);
 		}
 
 		msg
@@ -81,35 +340,90 @@ impl FollowUpRender for NextTurnRender {
 	}
 }
 
-impl NextTurnRender {
-    fn render_end_of_game(&self, desc: &mut MessageBuilder, msg: &mut CreateInteractionResponseFollowup) {
-        let (loser, winner) = self.state.turns();
+/// The public part of a sonar ping. What it found is only shown to the player who used it.
+#[derive(Clone)]
+pub struct SonarRender { pub state: Game, pub report: SonarReport }
+impl FollowUpRender for SonarRender {
+	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+		let target = self.state.player(self.report.target);
 
-        desc.push_bold('[')
-			.mention(&UserId(loser.user_id))
-			.push(" lost all their ships! ")
-			.mention(&UserId(winner.user_id))
-			.push(" wins!")
+		let mut desc = MessageBuilder::new();
+		desc.push_bold('[')
+			.mention(&UserId(self.report.shooter_id))
+			.push(" pinged the waters of ")
+			.push(mention_members(target))
+			.push(" around ")
+			.push_italic(Coord(self.report.center))
+			.push('.')
+			.push_bold(']')
+			.push('\n');
+
+		for &tile in &self.report.decoys {
+			push_exposed_decoy(&mut desc, tile);
+		}
+
+		render_next_turn(self.state, self.report.game_over, desc, msg)
+	}
+}
+
+#[derive(Clone)]
+pub struct SonarResultRender(pub SonarReport);
+impl FollowUpRender for SonarResultRender {
+	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+		let mut desc = MessageBuilder::new();
+		desc.push_bold('[');
+
+		if self.0.found {
+			desc.push("Contact! There are ships in the 3x3 area around ");
+		} else {
+			desc.push("No contact. There are no ships left in the 3x3 area around ");
+		}
+
+		desc.push_italic(Coord(self.0.center))
+			.push('.')
 			.push_bold(']');
 
-        let mut buffer = RenderTarget::new();
-        buffer.set_all_fields(loser);
+		msg
+		.ephemeral(true)
+		.embed(|e| e
+			.description(desc)
+			.color(EMBED_COLOR))
+	}
+}
 
-        msg.embed(|e| e
-			.description(
-				buffer.render_grid(
-					&format!("**[Loser]** {}", UserId(loser.user_id).mention()),
-					&Emotes::OWN))
-			.color(RETRY_COLOR));
+/// Tells everyone that a ship moved, but not which one or where.
+#[derive(Clone)]
+pub struct MoveRender { pub state: Game, pub report: MoveReport }
+impl FollowUpRender for MoveRender {
+	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+		let mut desc = MessageBuilder::new();
+		desc.push_bold('[')
+			.mention(&UserId(self.report.mover_id))
+			.push(" moved one of their ships instead of firing.")
+			.push_bold(']')
+			.push('\n');
+
+		render_next_turn(self.state, self.report.game_over, desc, msg)
+	}
+}
 
-        buffer = RenderTarget::new();
-        buffer.set_all_fields(winner);
+// Prompts the next player, unless that was the last turn of a points race
+fn render_next_turn<'a, 'b>(state: Game, game_over: bool, mut desc: MessageBuilder, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
+	if game_over {
+		render_end_of_game(&state, &[], &mut desc, msg);
+	} else {
+		desc.push_bold('[')
+			.mention(&UserId(state.current().shooter_id()))
+			.push(", it's your turn!")
+			.push_bold(']');
 
-        msg.embed(|e| e
-			.description(
-				buffer.render_grid(
-					&format!("**[Winner]** {}", UserId(winner.user_id).mention()),
-					&Emotes::OWN))
-			.color(EMBED_COLOR));
-    }
+		SharedPrepareRender(state, GameActionKind::StartTurn)
+		.render_follow_up(msg);
+	}
+
+	msg
+	.ephemeral(false)
+	.embed(|e| e
+		.description(desc)
+		.color(EMBED_COLOR))
 }
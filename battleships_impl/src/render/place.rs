@@ -1,23 +1,25 @@
 use serenity::builder::{CreateEmbed, CreateInteractionResponseData, CreateInteractionResponseFollowup, CreateComponents};
-use serenity::model::prelude::*;
-use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::component::{ButtonStyle, InputTextStyle};
 use serenity::utils::*;
 use battleships_model::game_state::*;
+use battleships_model::rules::TopologyRule;
 
-use crate::consts::EMBED_COLOR;
-use crate::action::{GameAction, GameActionKind};
+use crate::consts::{EMBED_COLOR, RETRY_COLOR};
+use crate::action::{GameAction, GameActionKind, Coord, Direction};
+use crate::store::Game;
 
 use super::{FollowUpRender, InteractionRender};
+use super::utility::mention::mention_members;
 use super::utility::render_target::*;
 use super::utility::renders::SharedPrepareRender;
 
 #[derive(Clone)]
-pub struct NextPlaceRender(pub GameState);
+pub struct NextPlaceRender(pub Game);
 impl FollowUpRender for NextPlaceRender {
 	fn render_follow_up<'a, 'b>(self, msg: &'b mut CreateInteractionResponseFollowup<'a>) -> &'b mut CreateInteractionResponseFollowup<'a> {
 		let mut desc = MessageBuilder::new();
 		desc.push_bold('[')
-			.mention(&UserId(self.0.current().user_id))
+			.push(mention_members(self.0.current()))
 			.push(", prepare as well!")
 			.push_bold(']');
 
@@ -31,21 +33,46 @@ impl FollowUpRender for NextPlaceRender {
 }
 
 #[derive(Clone)]
-pub struct PlaceRender(pub GameState);
+pub struct PlaceRender(pub Game);
 impl PlaceRender {
 	fn create_embed<'a>(&'_ self, embed: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
 		let player = self.0.current();
 
-		let mut buffer = RenderTarget::new();
+		let mut buffer = RenderTarget::new(self.0.rules());
 		buffer.set_ships(player);
+		buffer.set_traps(player);
+		buffer.set_headquarters(player);
+
+		let mut desc = buffer.render_grid("[Okay?]", &Emotes::OWN);
+		let armor = self.0.rules().armor;
+		for (index, ship) in player.ships().iter().enumerate() {
+			let pos = Coord(ship.state.position());
+			let dir = Direction(ship.state.rotation());
+			desc.push_str(&format!("\n**{}.** {} at *{pos}* ({dir})", index + 1, ship.info.label));
+			if armor.armor(ship.info) > 0 { desc.push_str(" \u{1F6E1}\u{FE0F}"); }
+			if player.is_flagship(index) { desc.push_str(" \u{1F451}"); }
+		}
+
+		// Traps are numbered after the ships
+		for (index, trap) in player.traps().iter().enumerate() {
+			let pos = Coord(trap.pos);
+			desc.push_str(&format!("\n**{}.** {} at *{pos}*", player.ships().len() + index + 1, trap.kind.label()));
+		}
+
+		// The headquarters come last
+		if let Some(pos) = player.headquarters() {
+			let num = player.ships().len() + player.traps().len() + 1;
+			desc.push_str(&format!("\n**{num}.** Headquarters at *{}*", Coord(pos)));
+		}
 
 		embed
-		.description(buffer.render_grid("[Okay?]", &Emotes::OWN))
+		.description(desc)
 		.color(EMBED_COLOR)
 	}
 
 	fn create_components(self, components: &mut CreateComponents) -> &mut CreateComponents {
 		let random_button_id = GameAction::new(GameActionKind::RandomizePlace, self.0.clone()).to_id();
+		let arrange_button_id = GameAction::new(GameActionKind::ArrangeShip, self.0.clone()).to_id();
 		let confirm_button_id = GameAction::new(GameActionKind::ConfirmPlace, self.0).to_id();
 
 		components
@@ -54,6 +81,10 @@ impl PlaceRender {
 				.custom_id(confirm_button_id)
 				.label("Confirm")
 				.style(ButtonStyle::Success))
+			.create_button(|b| b
+				.custom_id(arrange_button_id)
+				.label("Move Ship")
+				.style(ButtonStyle::Secondary))
 			.create_button(|b| b
 				.custom_id(random_button_id)
 				.label("Random")
@@ -77,4 +108,93 @@ impl FollowUpRender for PlaceRender {
 		.embed(|e| self.create_embed(e))
 		.components(|c| self.create_components(c))
 	}
-}
\ No newline at end of file
+}
+#[derive(Copy, Clone)]
+pub enum InvalidPlaceReason {
+	InvalidShip,
+	InvalidCoord,
+	InvalidDirection,
+	Placement(PlacementError)
+}
+
+#[derive(Clone)]
+pub struct InvalidPlaceRender(pub Game, pub InvalidPlaceReason);
+impl InteractionRender for InvalidPlaceRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let reason = match self.1 {
+			InvalidPlaceReason::InvalidShip => "**[**There is no ship with that number.**]**".to_owned(),
+			InvalidPlaceReason::InvalidCoord => "**[**That coordinate is invalid.**]**".to_owned(),
+			InvalidPlaceReason::InvalidDirection if is_hex(&self.0) => "**[**The direction has to be H, V or 2.**]**".to_owned(),
+			InvalidPlaceReason::InvalidDirection => "**[**The direction has to be H, V or 0-3 turns, optionally followed by M.**]**".to_owned(),
+			InvalidPlaceReason::Placement(err) => format!("**[**{err}**]**")
+		};
+
+		PlaceRender(self.0)
+		.render_interaction(msg)
+		.embed(|e| e
+			.description(reason)
+			.colour(RETRY_COLOR))
+	}
+}
+
+pub(crate) const ARRANGE_SHIP_TEXT_ID: &str = "ship";
+pub(crate) const ARRANGE_TILE_TEXT_ID: &str = "tile";
+pub(crate) const ARRANGE_DIRECTION_TEXT_ID: &str = "direction";
+
+#[derive(Clone)]
+pub struct ChooseArrangeRender(pub Game);
+impl InteractionRender for ChooseArrangeRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		let ship_count = self.0.current().ships().len();
+		let trap_count = self.0.current().traps().len();
+		let has_headquarters = self.0.current().headquarters().is_some();
+		let (tile_label, direction_label) =
+			if is_hex(&self.0) { ("First Tile", "Direction (H, V down-right, 2 down-left)") }
+			else { ("Top-Left Tile", "Direction (H/V, or 0-3 turns, M to mirror)") };
+		let custom_id = GameAction::new(GameActionKind::ArrangeShip, self.0).to_id();
+
+		let ship_label = match (trap_count, has_headquarters) {
+			(0, false) => format!("Ship (1-{ship_count})"),
+			(_, false) => format!("Ship or Trap (1-{})", ship_count + trap_count),
+			(0, true) => format!("Ship or HQ (1-{})", ship_count + 1),
+			(_, true) => format!("Ship, Trap or HQ (1-{})", ship_count + trap_count + 1)
+		};
+
+		msg
+		.custom_id(custom_id)
+		.title("Move Ship")
+		.components(|c| c
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(ARRANGE_SHIP_TEXT_ID)
+					.label(ship_label)
+					.placeholder("f.e. 1")
+					.min_length(1)
+					.max_length(2)
+					.style(InputTextStyle::Short)
+					.required(true)))
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(ARRANGE_TILE_TEXT_ID)
+					.label(tile_label)
+					.placeholder("f.e. B4")
+					.min_length(2)
+					.max_length(3)
+					.style(InputTextStyle::Short)
+					.required(true)))
+			.create_action_row(|r| r
+				.create_input_text(|i| i
+					.custom_id(ARRANGE_DIRECTION_TEXT_ID)
+					.label(direction_label)
+					.placeholder("f.e. H")
+					.min_length(1)
+					.max_length(10)
+					.style(InputTextStyle::Short)
+					.required(true))))
+	}
+}
+
+// Ships on hex boards lie along one of three axes instead of turning freely
+fn is_hex(state: &GameState) -> bool {
+	state.rules().topology == TopologyRule::Hex
+}
@@ -1,32 +1,65 @@
 use serenity::builder::CreateMessage;
 use serenity::model::prelude::*;
 use battleships_model::game_state::*;
+use battleships_model::rules::{GameMode, TerrainRule, VictoryRule};
 
 use crate::consts::{EMBED_COLOR, ERROR_COLOR};
 use crate::action::GameActionKind;
+use crate::store::Game;
 
 use super::MessageRender;
+use super::utility::mention::mention_members;
 use super::utility::renders::SharedPrepareRender;
 
 #[derive(Clone)]
-pub struct StartRender(pub GameState);
+pub struct StartRender(pub Game);
 impl MessageRender for StartRender {
 	fn render_message<'a, 'b>(self, msg: &'b mut CreateMessage<'a>) -> &'b mut CreateMessage<'a> {
-		let user_1_id = UserId(self.0.player_1.user_id).mention();
-		let user_2_id = UserId(self.0.player_2.user_id).mention();
+		let mentions: Vec<String> = self.0.players().iter()
+			.map(mention_members)
+			.collect();
+
+		let first = mention_members(&self.0.players()[0]);
+		let everyone = if self.0.rules().mode == GameMode::Teams {
+			mentions.join(" vs ")
+		} else {
+			let (last, rest) = mentions.split_last().expect("games have at least two players");
+			format!("{} & {last}", rest.join(", "))
+		};
+
+		// Random islands can be played again with the same seed
+		let mut desc = format!("**[**{everyone}, get ready for battle!**]**\n");
+		if let TerrainRule::Seeded(seed) = self.0.rules().terrain {
+			desc.push_str(&format!("**[**The islands were scattered with seed *{seed}*.**]**\n"));
+		}
+
+		match self.0.rules().victory {
+			VictoryRule::Annihilation => {}
+			VictoryRule::Flagship => desc.push_str("**[**Every fleet has a secret flagship. Whoever loses theirs is out!**]**\n"),
+			VictoryRule::Headquarters => desc.push_str("**[**Everyone hides their headquarters. Whoever loses theirs is out!**]**\n"),
+			VictoryRule::Points { rounds } => desc.push_str(&format!("**[**After {rounds} rounds, the most points win. Every tile of a sunk ship is worth one.**]**\n"))
+		}
+		desc.push_str(&format!("**[**{first}, you prepare first.**]**"));
 
 		SharedPrepareRender(self.0, GameActionKind::Place)
 		.render_message(msg)
 		.add_embed(|e| e
-			.description(format!("**[**{user_1_id} & {user_2_id}, get ready for battle!**]**\n**[**{user_1_id}, you prepare first.**]**"))
+			.description(desc)
 			.color(EMBED_COLOR))
 	}
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub enum FailStartReason {
 	Bot(UserId),
-	Same
+	Same,
+	PlayerCount,
+	TeamSize,
+	BoardSize,
+	FleetTooLarge,
+	Simultaneous,
+	HexShapes,
+	Rounds
 }
 
 #[derive(Clone)]
@@ -37,7 +70,14 @@ impl MessageRender for FailStartRender {
 		.add_embed(|e| e
 			.description(match self.0 {
 				FailStartReason::Bot(id) => format!("**[**{} is a bot and cannot play.**]**", id.mention()),
-    			FailStartReason::Same => format!("**[**You can't play against yourself.**]**"),
+				FailStartReason::Same => "**[**You can't play against yourself.**]**".to_owned(),
+				FailStartReason::TeamSize => format!("**[**Team battles need exactly {} players.**]**", GameState::TEAM_SIZE * 2),
+				FailStartReason::PlayerCount => format!("**[**A game needs between {} and {} players.**]**", GameState::MIN_PLAYERS, GameState::MAX_PLAYERS),
+				FailStartReason::BoardSize => format!("**[**The board size must be between {0}x{0} and {1}x{1}.**]**", BoardSize::MIN, BoardSize::MAX),
+				FailStartReason::FleetTooLarge => "**[**That fleet doesn't fit on a board this small.**]**".to_owned(),
+				FailStartReason::Simultaneous => "**[**Special weapons, depth charges and moving ships can't be used when everyone fires at once.**]**".to_owned(),
+				FailStartReason::HexShapes => "**[**Only straight ships fit on a hex board.**]**".to_owned(),
+				FailStartReason::Rounds => format!("**[**A points race lasts between 1 and {} rounds.**]**", VictoryRule::MAX_ROUNDS),
 			})
 			.color(ERROR_COLOR))
 	}
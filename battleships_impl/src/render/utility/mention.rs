@@ -0,0 +1,10 @@
+use serenity::model::prelude::*;
+use battleships_model::game_state::PlayerState;
+
+/// Mentions everyone playing on the board, f.e. "@A & @B" for a team.
+pub fn mention_members(player: &PlayerState) -> String {
+	player.members()
+		.map(|id| UserId(id).mention().to_string())
+		.collect::<Vec<_>>()
+		.join(" & ")
+}
@@ -1,2 +1,3 @@
 pub mod renders;
 pub mod render_target;
+pub mod mention;
@@ -1,4 +1,9 @@
-use battleships_model::game_state::{PlayerState, Vec2, GRID_SIZE};
+use battleships_model::depth::Layer;
+use battleships_model::game_state::{PlayerState, Vec2};
+use battleships_model::grid::Grid;
+use battleships_model::rules::{GameRules, LayerRule, TopologyRule};
+use battleships_model::terrain::TerrainKind;
+use battleships_model::trap::TrapKind;
 
 pub struct Emotes {
 	empty: &'static str,
@@ -7,7 +12,20 @@ pub struct Emotes {
 	ship_start: &'static str,
 	ship_end: &'static str,
 	ship_hit: &'static str,
-	ship_sunk: &'static str
+	ship_sunk: &'static str,
+	mine: &'static str,
+	mine_hit: &'static str,
+	decoy: &'static str,
+	decoy_hit: &'static str,
+	decoy_exposed: &'static str,
+	stale: &'static str,
+	damaged: &'static str,
+	island: &'static str,
+	island_hit: &'static str,
+	reef: &'static str,
+	reef_hit: &'static str,
+	headquarters: &'static str,
+	captured: &'static str
 }
 
 impl Emotes {
@@ -18,7 +36,22 @@ impl Emotes {
 		ship_start: "⬛",
 		ship_end: "⬛",
 		ship_hit: "🟥",
-		ship_sunk: "❌"
+		ship_sunk: "❌",
+		mine: "⬛",
+		mine_hit: "💥",
+		decoy: "⬛",
+		// Looks just like a hit ship until exposed
+		decoy_hit: "🟥",
+		decoy_exposed: "🎈",
+		// Still looks like the miss it was when fired at
+		stale: "🔷",
+		damaged: "🟧",
+		island: "🏝️",
+		island_hit: "🏜️",
+		reef: "🪨",
+		reef_hit: "🫧",
+		headquarters: "⬛",
+		captured: "🚩"
 	};
 
 	pub const OWN: Emotes = Emotes {
@@ -28,32 +61,81 @@ impl Emotes {
 		ship_start: "◻️",
 		ship_end: "◻️",
 		ship_hit: "🟥",
-		ship_sunk: "❌"
+		ship_sunk: "❌",
+		mine: "💣",
+		mine_hit: "💥",
+		decoy: "🎈",
+		decoy_hit: "🎭",
+		decoy_exposed: "💨",
+		stale: "💠",
+		damaged: "🟧",
+		island: "🏝️",
+		island_hit: "🏜️",
+		reef: "🪨",
+		reef_hit: "🫧",
+		headquarters: "🏰",
+		captured: "🚩"
 	};
 }
 
 bitflags::bitflags! {
 	#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-	struct RenderFlags: u8 {
+	struct RenderFlags: u16 {
 		const NONE = 0;
 		const HIT = 1;
 		const SUNK = 2;
 		const SHIP = 4;
 		const SHIP_START = 8;
 		const SHIP_END = 16;
+		const MINE = 32;
+		const DECOY = 64;
+		const EXPOSED = 128;
+		const STALE = 256;
+		const DAMAGED = 512;
+		const ISLAND = 1024;
+		const REEF = 2048;
+		const HEADQUARTERS = 4096;
 	}
 }
 
-const RENDER_SIZE: usize = GRID_SIZE as usize;
-
 #[derive(Debug, Clone)]
 pub struct RenderTarget {
-	buffer: [[RenderFlags; RENDER_SIZE]; RENDER_SIZE]
+	buffer: Vec<Vec<RenderFlags>>,
+	grid: Grid,
+	layer: Layer
 }
 
 impl RenderTarget {
-	pub fn new() -> Self {
-		RenderTarget { buffer: Default::default() }
+	/// An empty board, apart from the terrain everyone can see.
+	pub fn new(rules: &GameRules) -> Self {
+		Self::new_layer(rules, Layer::Surface)
+	}
+
+	/// Like [`RenderTarget::new`], but only ships in the given layer will be shown.
+	pub fn new_layer(rules: &GameRules, layer: Layer) -> Self {
+		let size = usize::from(rules.board_size.get());
+		let mut res = RenderTarget {
+			buffer: vec![vec![RenderFlags::NONE; size]; size],
+			grid: rules.grid(),
+			layer
+		};
+
+		let terrain = rules.terrain.layout(rules.board_size);
+		for pos in rules.board_size.tiles() {
+			if let Some(field) = res.get_field_mut(pos) {
+				*field |= match terrain.kind_at(pos) {
+					Some(TerrainKind::Island) => RenderFlags::ISLAND,
+					Some(TerrainKind::Reef) => RenderFlags::REEF,
+					None => RenderFlags::NONE
+				};
+			}
+		}
+
+		res
+	}
+
+	fn size(&self) -> u8 {
+		self.buffer.len() as u8
 	}
 
 	fn get_field_mut(&mut self, pos: Vec2) -> Option<&mut RenderFlags> {
@@ -63,31 +145,75 @@ impl RenderTarget {
 	pub fn set_all_fields(&mut self, player: &PlayerState) {
 		self.set_hits(player);
 		self.set_ships(player);
+		self.set_traps(player);
+		self.set_headquarters(player);
+	}
+
+	pub fn set_headquarters(&mut self, player: &PlayerState) {
+		if self.layer != Layer::Surface { return; }
+
+		if let Some(field) = player.headquarters().and_then(|pos| self.get_field_mut(pos)) {
+			*field |= RenderFlags::HEADQUARTERS;
+		}
+	}
+
+	pub fn set_traps(&mut self, player: &PlayerState) {
+		// Traps float on the surface
+		if self.layer != Layer::Surface { return; }
+
+		for trap in player.traps() {
+			if let Some(field) = self.get_field_mut(trap.pos) {
+				*field |= match trap.kind {
+					TrapKind::Mine => RenderFlags::MINE,
+					TrapKind::Decoy if trap.exposed => RenderFlags::DECOY | RenderFlags::EXPOSED,
+					TrapKind::Decoy => RenderFlags::DECOY
+				};
+			}
+		}
 	}
 
 	pub fn set_hits(&mut self, player: &PlayerState) {
-		for x in 0..GRID_SIZE {
-			for y in 0..GRID_SIZE {
-				if player.hits.get(Vec2::new(x, y)) {
+		for x in 0..self.size() {
+			for y in 0..self.size() {
+				// An old miss with a ship on it now is no hit on that ship
+				let pos = Vec2::new(x, y);
+				if self.layer == Layer::Depth {
+					if player.is_hit(pos, Layer::Depth) {
+						self.buffer[y as usize][x as usize] |= RenderFlags::HIT;
+					}
+				} else if player.is_stale(pos) {
+					self.buffer[y as usize][x as usize] |= RenderFlags::STALE;
+				} else if player.hits.get(pos) {
 					self.buffer[y as usize][x as usize] |= RenderFlags::HIT;
+				} else if player.damage(pos) > 0 {
+					// Armor that took a hit, but wasn't destroyed yet
+					self.buffer[y as usize][x as usize] |= RenderFlags::DAMAGED;
 				}
 			}
 		}
 	}
 
 	pub fn set_ships(&mut self, player: &PlayerState) {
-		for ship in player.ships() {
+		let layer = self.layer;
+		for ship in player.ships().iter().filter(|s| s.layer() == layer) {
 			// Track whether the ship was sunk
-			let sunk = player.is_sunk(&ship);
-			let len = usize::from(ship.info.len);
+			let sunk = player.is_sunk(ship);
+			let mut is_first_end = true;
 
 			// Mark parts as ship with appropriate flags.
-			for (i, pos) in ship.tiles().enumerate() {
+			// Ends are tiles with at most one neighbour in the same ship, the first one is the start.
+			for pos in ship.tiles() {
+				// Ships crossing the edge of a wrapping board stay connected
+				let neighbours = ship.tiles()
+					.filter(|&t| self.grid.are_neighbours(t, pos))
+					.count();
+
 				if let Some(field) = self.get_field_mut(pos) {
 					let mut flags =
-						if i == 0 { RenderFlags::SHIP | RenderFlags::SHIP_START }
-						else if i >= len - 1 { RenderFlags::SHIP | RenderFlags::SHIP_END }
-						else { RenderFlags::SHIP };
+						if neighbours > 1 { RenderFlags::SHIP }
+						else if is_first_end { RenderFlags::SHIP | RenderFlags::SHIP_START }
+						else { RenderFlags::SHIP | RenderFlags::SHIP_END };
+					if neighbours <= 1 { is_first_end = false; }
 					if sunk { flags |= RenderFlags::SUNK; }
 
 					*field |= flags;
@@ -97,28 +223,49 @@ impl RenderTarget {
 	}
 
 	pub fn render_grid(&self, title: &str, emotes: &Emotes) -> String {
-		const HEADER: &str = "\n🌊\u{feff}🇦\u{feff}🇧\u{feff}🇨\u{feff}🇩\u{feff}🇪\u{feff}🇫\u{feff}🇬\u{feff}🇭\u{feff}🇮\u{feff}🇯\n";
-		const NUMS: &[&str] = &[
-			"1\u{fe0f}\u{20e3}", "2\u{fe0f}\u{20e3}", "3\u{fe0f}\u{20e3}",
-			"4\u{fe0f}\u{20e3}", "5\u{fe0f}\u{20e3}", "6\u{fe0f}\u{20e3}",
-			"7\u{fe0f}\u{20e3}", "8\u{fe0f}\u{20e3}", "9\u{fe0f}\u{20e3}",
-			"🔟"
-		];
+		// Boards larger than 10 use two keycaps per row number,
+		// so the header needs an extra corner tile to stay aligned.
+		let wide = self.buffer.len() > 10;
 
 		let mut res = String::new();
 		res.push_str(title);
-		res.push_str(HEADER);
+		res.push('\n');
+		res.push_str(if wide { "🌊🌊" } else { "🌊" });
+
+		for column in 0..self.size() {
+			res.push('\u{feff}');
+			res.push(char::from_u32(REGIONAL_A + u32::from(column)).unwrap_or('?'));
+		}
+
+		res.push('\n');
 
 		for (index, row) in self.buffer.iter().enumerate() {
-			if let Some(&num) = NUMS.get(index) {
-				res.push_str(num);
+			push_row_number(&mut res, index + 1, wide);
+
+			// Shifting every other row by half a tile makes the squares line up like hexes
+			if self.grid.topology() == TopologyRule::Hex && index % 2 == 1 {
+				res.push_str(HALF_TILE);
 			}
 
 			for &field in row {
 				res.push_str(
 					if field == RenderFlags::NONE { emotes.empty }
+					// Shots at terrain are wasted, but still show so nobody fires there twice
+					else if field.contains(RenderFlags::ISLAND | RenderFlags::HIT) { emotes.island_hit }
+					else if field.contains(RenderFlags::ISLAND) { emotes.island }
+					else if field.contains(RenderFlags::REEF | RenderFlags::HIT) { emotes.reef_hit }
+					else if field.contains(RenderFlags::REEF) { emotes.reef }
+					else if field.contains(RenderFlags::HEADQUARTERS | RenderFlags::HIT) { emotes.captured }
+					else if field.contains(RenderFlags::HEADQUARTERS) { emotes.headquarters }
 					else if field == RenderFlags::HIT { emotes.miss }
+					else if field.contains(RenderFlags::MINE | RenderFlags::HIT) { emotes.mine_hit }
+					else if field.contains(RenderFlags::MINE) { emotes.mine }
+					else if field.contains(RenderFlags::DECOY | RenderFlags::EXPOSED) { emotes.decoy_exposed }
+					else if field.contains(RenderFlags::DECOY | RenderFlags::HIT) { emotes.decoy_hit }
+					else if field.contains(RenderFlags::DECOY) { emotes.decoy }
+					else if field.contains(RenderFlags::STALE) { emotes.stale }
 					else if field.contains(RenderFlags::SUNK) { emotes.ship_sunk }
+					else if field.contains(RenderFlags::DAMAGED) { emotes.damaged }
 					else if field.contains(RenderFlags::SHIP | RenderFlags::HIT) { emotes.ship_hit }
 					else if field.contains(RenderFlags::SHIP_START) { emotes.ship_start }
 					else if field.contains(RenderFlags::SHIP_END) { emotes.ship_end }
@@ -132,3 +279,42 @@ impl RenderTarget {
 		res
 	}
 }
+
+/// Renders the board of the player, with the depth below it if submarines dive.
+pub fn render_layers(rules: &GameRules, player: &PlayerState, title: &str, emotes: &Emotes) -> String {
+	let mut buffer = RenderTarget::new(rules);
+	buffer.set_all_fields(player);
+	let mut res = buffer.render_grid(title, emotes);
+
+	if rules.layers == LayerRule::Submerged {
+		let mut buffer = RenderTarget::new_layer(rules, Layer::Depth);
+		buffer.set_all_fields(player);
+		res.push_str(&buffer.render_grid("*Depth*", emotes));
+	}
+
+	res
+}
+
+// The regional indicator symbol for 'A', the others follow in order
+const REGIONAL_A: u32 = 0x1F1E6;
+
+// An en space and a thin space, which come close to half the width of an emote
+const HALF_TILE: &str = "\u{2002}\u{2009}";
+
+fn push_row_number(res: &mut String, num: usize, wide: bool) {
+	const KEYCAP: &str = "\u{fe0f}\u{20e3}";
+
+	if !wide {
+		if num == 10 {
+			res.push('🔟');
+		} else {
+			res.push_str(&num.to_string());
+			res.push_str(KEYCAP);
+		}
+	} else {
+		for digit in format!("{num:02}").chars() {
+			res.push(digit);
+			res.push_str(KEYCAP);
+		}
+	}
+}
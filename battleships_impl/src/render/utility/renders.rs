@@ -1,8 +1,8 @@
 use serenity::builder::{CreateMessage, CreateInteractionResponseData, CreateInteractionResponseFollowup, CreateComponents};
 use serenity::model::prelude::component::ButtonStyle;
-use battleships_model::game_state::*;
 
 use crate::action::{GameAction, GameActionKind};
+use crate::store::Game;
 
 use super::super::{MessageRender, InteractionRender, FollowUpRender};
 
@@ -33,7 +33,7 @@ impl MessageRender for RemoveButtonsRender {
 }
 
 #[derive(Clone)]
-pub struct SharedPrepareRender(pub GameState, pub GameActionKind);
+pub struct SharedPrepareRender(pub Game, pub GameActionKind);
 impl SharedPrepareRender {
 	fn create_components(self, components: &mut CreateComponents) -> &mut CreateComponents {
 		let button_id = GameAction::new(self.1, self.0).to_id();
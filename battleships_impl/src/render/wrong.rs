@@ -1,16 +1,18 @@
+use std::fmt::Display;
+
 use serenity::builder::CreateInteractionResponseData;
+
+use battleships_model::phase::PhaseError;
+
 use crate::consts::ERROR_COLOR;
+use crate::store::StoreError;
 use super::InteractionRender;
 
 #[derive(Clone)]
 pub struct NotYourTurnRender;
 impl InteractionRender for NotYourTurnRender {
 	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
-		msg
-		.ephemeral(true)
-		.embed(|e| e
-			.description("**[**It is not your turn.**]**")
-			.color(ERROR_COLOR))
+		render_error(msg, "It is not your turn.")
 	}
 }
 
@@ -18,10 +20,33 @@ impl InteractionRender for NotYourTurnRender {
 pub struct NotInvolvedRender;
 impl InteractionRender for NotInvolvedRender {
 	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
-		msg
-		.ephemeral(true)
-		.embed(|e| e
-			.description("**[**You're not involved in this.**]**")
-			.color(ERROR_COLOR))
+		render_error(msg, "You're not involved in this.")
+	}
+}
+
+/// A button or modal from another phase of the game, like a stale or crafted one.
+#[derive(Clone)]
+pub struct WrongPhaseRender(pub PhaseError);
+impl InteractionRender for WrongPhaseRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		render_error(msg, self.0)
 	}
 }
+
+/// A button or modal that refers to an older state of the game, or to a game that is over.
+#[derive(Clone)]
+pub struct StaleGameRender(pub StoreError);
+impl InteractionRender for StaleGameRender {
+	fn render_interaction<'a, 'b>(self, msg: &'b mut CreateInteractionResponseData<'a>) -> &'b mut CreateInteractionResponseData<'a> {
+		render_error(msg, self.0)
+	}
+}
+
+// Only the player who clicked sees these
+fn render_error<'a, 'b>(msg: &'b mut CreateInteractionResponseData<'a>, text: impl Display) -> &'b mut CreateInteractionResponseData<'a> {
+	msg
+	.ephemeral(true)
+	.embed(|e| e
+		.description(format!("**[**{text}**]**"))
+		.color(ERROR_COLOR))
+}
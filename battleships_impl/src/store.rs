@@ -0,0 +1,442 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serenity::prelude::TypeMapKey;
+
+use battleships_model::encode::Encode;
+use battleships_model::game_state::GameState;
+
+/// Where a game is stored, and how often it changed since. Buttons carry this instead of the game itself,
+/// so a button from before the last change can be told apart from a current one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GameKey {
+	pub id: u64,
+	pub version: u32
+}
+
+/// A game along with the key it was loaded by.
+#[derive(Debug, Clone)]
+pub struct Game {
+	pub key: GameKey,
+	pub state: GameState
+}
+
+/// Why a game couldn't be loaded or saved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StoreError {
+	/// The game is over, or was never stored here.
+	Missing,
+	/// The game changed since the key was handed out.
+	Stale
+}
+
+/// Keeps every running game. The state is too large for the buttons, so they only refer to it.
+///
+/// With a directory, every game is written to a file as well, so it survives a restart.
+/// Only [`GameStore::with_dir`], [`GameStore::persist`] and [`GameStore::evict_idle`] touch the files,
+/// everything else works on the games in memory.
+#[derive(Debug, Default)]
+pub struct GameStore {
+	games: Mutex<Games>,
+	dir: Option<PathBuf>,
+	// Held while writing files, so an older snapshot can't overwrite a newer one
+	io: Mutex<()>
+}
+
+#[derive(Debug, Default)]
+struct Games {
+	running: HashMap<u64, Slot>,
+	/// Adopted games that are over, so their old buttons can't start them again.
+	retired: HashSet<u64>
+}
+
+#[derive(Debug)]
+struct Slot {
+	version: u32,
+	state: GameState,
+	used: Instant
+}
+
+impl TypeMapKey for GameStore {
+	type Value = Arc<GameStore>;
+}
+
+impl GameKey {
+	/// The length of a key in bytes, see [`GameKey::to_bytes`].
+	pub const LEN: usize = 12;
+
+	pub fn to_bytes(self) -> [u8; Self::LEN] {
+		let mut res = [0; Self::LEN];
+		res[..8].copy_from_slice(&self.id.to_be_bytes());
+		res[8..].copy_from_slice(&self.version.to_be_bytes());
+		res
+	}
+
+	pub fn from_bytes(data: &[u8]) -> Option<GameKey> {
+		let id = u64::from_be_bytes(data.get(..8)?.try_into().ok()?);
+		let version = u32::from_be_bytes(data.get(8..Self::LEN)?.try_into().ok()?);
+		Some(GameKey { id, version })
+	}
+
+	/// Games adopted from old buttons have this bit set in their id, new games never do.
+	const ADOPTED: u64 = 1 << 63;
+
+	fn is_adopted(self) -> bool {
+		self.id & Self::ADOPTED != 0
+	}
+}
+
+impl Deref for Game {
+	type Target = GameState;
+
+	fn deref(&self) -> &GameState {
+		&self.state
+	}
+}
+
+impl DerefMut for Game {
+	fn deref_mut(&mut self) -> &mut GameState {
+		&mut self.state
+	}
+}
+
+impl GameStore {
+	/// How long a game may go without a click before [`GameStore::evict_idle`] drops it.
+	pub const IDLE_LIMIT: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+	/// A store that forgets every game when the bot stops.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// A store that also keeps every game as a file in the directory. The games already in there are read right away.
+	pub fn with_dir(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+		let dir = dir.into();
+		fs::create_dir_all(&dir)?;
+
+		let mut games = Games::default();
+		for entry in fs::read_dir(&dir)? {
+			let path = entry?.path();
+			let Some(id) = path.file_name()
+				.and_then(|name| name.to_str()?.strip_suffix(".bs"))
+				.and_then(|id| u64::from_str_radix(id, 16).ok()) else { continue; };
+
+			let data = fs::read(&path)?;
+			if data.is_empty() {
+				games.retired.insert(id);
+				continue;
+			}
+
+			// The file was last written when the game was last changed
+			let idle = fs::metadata(&path)?.modified()?.elapsed().unwrap_or_default();
+			match read_slot(&data) {
+				Some((version, state)) => {
+					let used = Instant::now().checked_sub(idle).unwrap_or_else(Instant::now);
+					games.running.insert(id, Slot { version, state, used });
+				}
+				None => eprintln!("Skipping unreadable game {}", path.display())
+			}
+		}
+
+		Ok(GameStore { games: Mutex::new(games), dir: Some(dir), io: Mutex::default() })
+	}
+
+	/// Stores a new game under a fresh key. It still has to be [persisted](GameStore::persist).
+	pub fn create(&self, state: GameState) -> Game {
+		let mut games = self.lock();
+
+		let mut id = rand::random::<u64>() & !GameKey::ADOPTED;
+		while games.running.contains_key(&id) {
+			id = rand::random::<u64>() & !GameKey::ADOPTED;
+		}
+
+		let key = GameKey { id, version: 0 };
+		games.running.insert(id, Slot { version: key.version, state: state.clone(), used: Instant::now() });
+
+		Game { key, state }
+	}
+
+	/// Stores a game from a button that carries the entire state, from before the games were stored.
+	///
+	/// The id is taken from the button, so clicking it again finds the same game instead of starting it over.
+	/// Once that game changed, the button is stale like any other.
+	pub fn adopt(&self, data: &[u8], state: GameState) -> Result<Game, StoreError> {
+		let key = GameKey { id: fnv1a(data) | GameKey::ADOPTED, version: 0 };
+
+		let mut games = self.lock();
+		if games.retired.contains(&key.id) {
+			return Err(StoreError::Missing);
+		}
+		games.running.entry(key.id).or_insert_with(|| Slot { version: key.version, state, used: Instant::now() });
+		drop(games);
+
+		self.load(key)
+	}
+
+	/// Loads the game, as long as it didn't change since the key was handed out.
+	pub fn load(&self, key: GameKey) -> Result<Game, StoreError> {
+		let mut games = self.lock();
+
+		let slot = games.running.get_mut(&key.id).ok_or(StoreError::Missing)?;
+		if slot.version != key.version {
+			return Err(StoreError::Stale);
+		}
+
+		slot.used = Instant::now();
+		Ok(Game { key, state: slot.state.clone() })
+	}
+
+	/// Replaces the stored game by a changed one, which gets a new key. Games that are over are dropped.
+	/// The change still has to be [persisted](GameStore::persist).
+	///
+	/// Fails if the game changed since it was loaded, like when a button was clicked twice at once.
+	pub fn save(&self, game: Game) -> Result<Game, StoreError> {
+		let mut games = self.lock();
+
+		let slot = games.running.get_mut(&game.key.id).ok_or(StoreError::Missing)?;
+		if slot.version != game.key.version {
+			return Err(StoreError::Stale);
+		}
+
+		let key = GameKey { id: game.key.id, version: slot.version.wrapping_add(1) };
+		if game.state.is_over() {
+			games.retire(key);
+		} else {
+			*slot = Slot { version: key.version, state: game.state.clone(), used: Instant::now() };
+		}
+
+		Ok(Game { key, state: game.state })
+	}
+
+	/// Drops every game nobody clicked on for longer than the limit, along with its file.
+	///
+	/// This blocks on the file system, so async code should run it on a blocking thread.
+	pub fn evict_idle(&self, limit: Duration) {
+		let idle: Vec<GameKey> = {
+			let mut games = self.lock();
+			let idle: Vec<GameKey> = games.running.iter()
+				.filter(|(_, slot)| slot.used.elapsed() > limit)
+				.map(|(&id, slot)| GameKey { id, version: slot.version })
+				.collect();
+			for &key in &idle {
+				games.retire(key);
+			}
+			idle
+		};
+
+		for key in idle {
+			self.persist(key.id);
+		}
+	}
+
+	/// Writes the game as it is right now to its file, or removes the file once the game is gone.
+	/// Without a directory, this does nothing.
+	///
+	/// This blocks on the file system, so async code should run it on a blocking thread.
+	pub fn persist(&self, id: u64) {
+		let Some(dir) = &self.dir else { return; };
+		let path = dir.join(format!("{id:016x}.bs"));
+
+		let _io = self.io.lock().expect("game store poisoned");
+		// Files hold the version and the length of the encoded state, followed by the state and its log as JSON.
+		// Retired games leave an empty file behind
+		let data = {
+			let games = self.lock();
+			match games.running.get(&id) {
+				Some(slot) => {
+					let state = slot.state.encode();
+					let mut data = slot.version.to_be_bytes().to_vec();
+					data.extend((state.len() as u32).to_be_bytes());
+					data.extend(state);
+					data.extend(serde_json::to_vec(slot.state.events()).expect("events always serialize"));
+					Some(data)
+				}
+				None => games.retired.contains(&id).then(Vec::new)
+			}
+		};
+
+		let res = match data {
+			Some(data) => fs::write(&path, data),
+			None => fs::remove_file(&path).or_else(|err| if err.kind() == ErrorKind::NotFound { Ok(()) } else { Err(err) })
+		};
+		if let Err(err) = res {
+			eprintln!("Failed to write {}: {err}", path.display());
+		}
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, Games> {
+		self.games.lock().expect("game store poisoned")
+	}
+}
+
+impl Games {
+	fn retire(&mut self, key: GameKey) {
+		self.running.remove(&key.id);
+		if key.is_adopted() {
+			self.retired.insert(key.id);
+		}
+	}
+}
+
+fn read_slot(data: &[u8]) -> Option<(u32, GameState)> {
+	let version = u32::from_be_bytes(data.get(..4)?.try_into().ok()?);
+	let len = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+	let (state, events) = data[8..].split_at_checked(len)?;
+
+	let mut state = GameState::try_decode(state).ok()?;
+	state.restore_events(serde_json::from_slice(events).ok()?);
+	Some((version, state))
+}
+
+// 64 bit FNV-1a, which unlike the std hasher stays the same across builds and restarts
+fn fnv1a(data: &[u8]) -> u64 {
+	data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+impl Display for StoreError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			StoreError::Missing => write!(f, "This game is over or no longer exists."),
+			StoreError::Stale => write!(f, "This button is out of date.")
+		}
+	}
+}
+
+impl Error for StoreError {}
+
+#[cfg(test)]
+mod tests {
+	use battleships_model::phase::Phase;
+	use battleships_model::rules::GameRules;
+
+	use super::*;
+
+	// A directory for one test, removed again even if the test fails
+	struct TempDir(PathBuf);
+
+	impl TempDir {
+		fn new() -> Self {
+			TempDir(std::env::temp_dir().join(format!("battleships-store-{:016x}", rand::random::<u64>())))
+		}
+	}
+
+	impl Drop for TempDir {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.0);
+		}
+	}
+
+	fn new_state() -> GameState {
+		GameState::new(&[1, 2], GameRules::default()).unwrap()
+	}
+
+	fn new_game(store: &GameStore) -> Game {
+		store.create(new_state())
+	}
+
+	#[test]
+	fn old_keys_are_stale() {
+		let store = GameStore::new();
+		let game = new_game(&store);
+		let old = game.key;
+
+		// Once the first player confirmed, their placement buttons refer to an older version
+		let mut changed = store.load(old).unwrap();
+		assert_eq!(changed.confirm_placement(), Ok(Phase::AwaitingPlacement(changed.turn())));
+		let changed = store.save(changed).unwrap();
+		assert_ne!(changed.key, old);
+
+		assert_eq!(store.load(old).unwrap_err(), StoreError::Stale);
+		assert_eq!(store.save(game).unwrap_err(), StoreError::Stale);
+		assert_eq!(store.load(changed.key).unwrap().phase(), changed.phase());
+	}
+
+	#[test]
+	fn unknown_keys_are_missing() {
+		let store = GameStore::new();
+		let game = new_game(&store);
+
+		let key = GameKey { id: game.key.id.wrapping_add(1), version: 0 };
+		assert_eq!(store.load(key).unwrap_err(), StoreError::Missing);
+	}
+
+	#[test]
+	fn keys_round_trip() {
+		let key = GameKey { id: 0x0123_4567_89AB_CDEF, version: 42 };
+		assert_eq!(GameKey::from_bytes(&key.to_bytes()), Some(key));
+		assert_eq!(GameKey::from_bytes(&key.to_bytes()[1..]), None);
+	}
+
+	#[test]
+	fn old_buttons_are_adopted_once() {
+		let store = GameStore::new();
+		let state = new_state();
+		let data = state.encode();
+
+		let game = store.adopt(&data, state.clone()).unwrap();
+		assert!(game.key.is_adopted());
+		assert_eq!(store.adopt(&data, state.clone()).unwrap().key, game.key);
+
+		// After the first change, the old button is as stale as any other
+		let mut changed = game;
+		changed.confirm_placement().unwrap();
+		store.save(changed).unwrap();
+		assert_eq!(store.adopt(&data, state).unwrap_err(), StoreError::Stale);
+	}
+
+	#[test]
+	fn idle_games_are_evicted() {
+		let store = GameStore::new();
+		let idle = new_game(&store);
+		let adopted = store.adopt(b"old button", new_state()).unwrap();
+
+		store.evict_idle(Duration::ZERO);
+		assert_eq!(store.load(idle.key).unwrap_err(), StoreError::Missing);
+		// Adopted games can't be brought back by their old buttons either
+		assert_eq!(store.adopt(b"old button", new_state()).unwrap_err(), StoreError::Missing);
+		assert_eq!(store.load(adopted.key).unwrap_err(), StoreError::Missing);
+	}
+
+	#[test]
+	fn saved_games_survive_a_restart() {
+		let dir = TempDir::new();
+		let first = GameStore::with_dir(&dir.0).unwrap();
+		let game = new_game(&first);
+		first.persist(game.key.id);
+
+		// A new store in the same directory stands in for the bot after a restart
+		let store = GameStore::with_dir(&dir.0).unwrap();
+		let mut loaded = store.load(game.key).unwrap();
+		assert_eq!(loaded.encode(), game.encode());
+		assert_eq!(loaded.events(), game.events());
+
+		loaded.confirm_placement().unwrap();
+		let saved = store.save(loaded).unwrap();
+		store.persist(saved.key.id);
+		let reloaded = GameStore::with_dir(&dir.0).unwrap().load(saved.key).unwrap();
+		assert_eq!(reloaded.encode(), saved.encode());
+		assert_eq!(reloaded.events(), saved.events());
+
+		store.evict_idle(Duration::ZERO);
+		assert_eq!(GameStore::with_dir(&dir.0).unwrap().load(saved.key).unwrap_err(), StoreError::Missing);
+	}
+
+	#[test]
+	fn retired_games_survive_a_restart() {
+		let dir = TempDir::new();
+		let store = GameStore::with_dir(&dir.0).unwrap();
+		let game = store.adopt(b"old button", new_state()).unwrap();
+		store.persist(game.key.id);
+
+		store.evict_idle(Duration::ZERO);
+		let store = GameStore::with_dir(&dir.0).unwrap();
+		assert_eq!(store.adopt(b"old button", new_state()).unwrap_err(), StoreError::Missing);
+	}
+}
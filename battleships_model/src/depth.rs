@@ -0,0 +1,36 @@
+use crate::game_state::{GameState, Vec2};
+use crate::rules::{LayerRule, TurnRule};
+use crate::shot::{FireError, FireReport};
+
+/// Where a ship sails. Regular shots only reach the surface, depth charges only reach below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Layer {
+	#[default]
+	Surface,
+	Depth
+}
+
+impl GameState {
+	/// Drops a depth charge instead of firing regular shots.
+	/// It goes off below the chosen tile and the tiles next to it, where only submerged ships are.
+	/// Like firing, this passes the turn as needed.
+	pub fn depth_charge(&mut self, pos: Vec2) -> Result<FireReport, FireError> {
+		self.phase().check_battle().map_err(FireError::Phase)?;
+
+		// Depth charges take up a whole turn, so they can't be locked in with everyone else's shots
+		if self.rules().layers != LayerRule::Submerged || self.rules().turns == TurnRule::Simultaneous {
+			return Err(FireError::NoDepthCharges);
+		}
+		if !self.rules().board_size.contains(pos) { return Err(FireError::OutOfBounds(pos)); }
+
+		let grid = self.rules().grid();
+		let tiles: Vec<Vec2> = std::iter::once(pos)
+			.chain(grid.neighbours(pos))
+			.filter(|&t| self.target().is_open(t, Layer::Depth, self.rules().movement))
+			.collect();
+		if tiles.is_empty() { return Err(FireError::NothingToHit); }
+
+		Ok(self.resolve_shots(&tiles, None, Layer::Depth))
+	}
+}
@@ -1,8 +1,267 @@
-pub trait Encode 
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::game_state::{PlacementError, Turn};
+
+pub trait Encode
 where Self: Sized {
 	type Out;
 	type In: ?Sized;
 
 	fn encode(&self) -> Self::Out;
-	fn try_decode(data: &Self::In) -> Option<Self>;
+	fn try_decode(data: &Self::In) -> Result<Self, DecodeError>;
+}
+
+/// Why data couldn't be decoded, or what is wrong with a state, see [`GameState::validate`](crate::game_state::GameState::validate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+	/// The data ended before everything was read, or went on afterwards.
+	Length,
+	/// The data isn't in any [`Format`].
+	UnknownVersion,
+	/// The checksum doesn't match, so the data was changed along the way.
+	Checksum,
+	/// The rules or fleet don't describe a game that can be played, or a board doesn't follow them.
+	Rules,
+	PlayerCount,
+	/// The turn is no player of the game or one that can't have the turn right now, or rounds are counted outside of a points race.
+	Turn,
+	/// The target is no opponent of the current player.
+	Target,
+	/// The phase doesn't match the state of the boards.
+	Phase,
+	/// A ship sticks out of the board, or can't face that way.
+	ShipOutOfBounds { player: Turn, index: usize },
+	OverlappingShips { player: Turn, index: usize, other: usize },
+	/// A ship, trap or the headquarters break some other placement rule.
+	Placement { player: Turn, error: PlacementError },
+	/// Hits are where nothing could have been hit.
+	Hits { player: Turn },
+	/// Armor took more hits than it has, or is where there is none.
+	Damage { player: Turn },
+	/// There are more charges left than the rules hand out.
+	Charges { player: Turn },
+	/// The teammate is missing in a team battle, or there is one in a free for all.
+	Teammate { player: Turn },
+	/// The flagship, headquarters or points don't match the victory rule.
+	Objective { player: Turn },
+	/// Shots are locked in that don't fit the rules, or aim at nobody.
+	PendingShots { player: Turn }
+}
+
+/// The versions of the format game states were encoded in. Buttons keep their ids for as long as the message is around,
+/// so every version that was ever sent still has to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// Two players with the classic fleet on a 10x10 board, in [`Format::V1_LEN`] bytes without a tag or checksum.
+	V1,
+	/// [`Format::V2_TAG`], the bit stream and a checksum over both.
+	V2
+}
+
+impl Format {
+	pub const V1_LEN: usize = 59;
+	/// Version 1 starts with a user id, which won't get anywhere close to this for a long time.
+	pub const V2_TAG: u8 = 0xB2;
+
+	/// Tells the format by the tag, or by the length for data from before there were tags.
+	pub fn detect(data: &[u8]) -> Option<Self> {
+		match data.first() {
+			Some(&Self::V2_TAG) => Some(Format::V2),
+			_ if data.len() == Self::V1_LEN => Some(Format::V1),
+			_ => None
+		}
+	}
+}
+
+/// Tags a bit stream and appends the checksum, see [`Format::V2`].
+pub(crate) fn seal(body: Vec<u8>) -> Vec<u8> {
+	let mut res = Vec::with_capacity(body.len() + 3);
+	res.push(Format::V2_TAG);
+	res.extend(body);
+	res.extend(checksum(&res).to_be_bytes());
+	res
+}
+
+/// Checks the tag and checksum of sealed data, returning the bit stream in between.
+pub(crate) fn unseal(data: &[u8]) -> Result<&[u8], DecodeError> {
+	if data.len() < 3 { return Err(DecodeError::Length); }
+
+	let (rest, sum) = data.split_at(data.len() - 2);
+	if Format::detect(rest) != Some(Format::V2) { return Err(DecodeError::UnknownVersion); }
+	if checksum(rest).to_be_bytes() != sum { return Err(DecodeError::Checksum); }
+
+	Ok(&rest[1..])
+}
+
+/// CRC-16/CCITT-FALSE, which catches any mangled byte of an id.
+fn checksum(data: &[u8]) -> u16 {
+	let mut crc: u16 = 0xFFFF;
+	for &byte in data {
+		crc ^= u16::from(byte) << 8;
+		for _ in 0..8 {
+			crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+		}
+	}
+
+	crc
+}
+
+/// Packs values into a big-endian bit stream.
+/// The last byte is padded with zeroes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BitWriter {
+	bytes: Vec<u8>,
+	len: usize
+}
+
+/// Reads values back from a stream created by [`BitWriter`].
+#[derive(Debug, Clone)]
+pub(crate) struct BitReader<'a> {
+	bytes: &'a [u8],
+	pos: usize
+}
+
+impl BitWriter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn write(&mut self, value: u64, bits: u8) {
+		for i in (0..bits).rev() {
+			self.write_bool((value >> i) & 1 != 0);
+		}
+	}
+
+	pub fn write_bool(&mut self, value: bool) {
+		if self.len == self.bytes.len() * 8 { self.bytes.push(0); }
+		if value {
+			let last = self.bytes.len() - 1;
+			self.bytes[last] |= 0x80 >> (self.len % 8);
+		}
+
+		self.len += 1;
+	}
+
+	pub fn finish(self) -> Vec<u8> {
+		self.bytes
+	}
+}
+
+impl<'a> BitReader<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		Self { bytes, pos: 0 }
+	}
+
+	pub fn read(&mut self, bits: u8) -> Result<u64, DecodeError> {
+		let mut res = 0;
+		for _ in 0..bits {
+			res = (res << 1) | u64::from(self.read_bool()?);
+		}
+
+		Ok(res)
+	}
+
+	pub fn read_bool(&mut self) -> Result<bool, DecodeError> {
+		let byte = self.bytes.get(self.pos / 8).ok_or(DecodeError::Length)?;
+		let res = byte & (0x80 >> (self.pos % 8)) != 0;
+		self.pos += 1;
+		Ok(res)
+	}
+
+	/// Whether everything but the padding of the last byte was read.
+	pub fn is_at_end(&self) -> bool {
+		self.pos.div_ceil(8) == self.bytes.len()
+	}
+
+	/// Checks that nothing but padding is left.
+	pub fn finish(&self) -> Result<(), DecodeError> {
+		if self.is_at_end() { Ok(()) } else { Err(DecodeError::Length) }
+	}
+}
+
+impl Display for DecodeError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DecodeError::Length => write!(f, "The data has the wrong length."),
+			DecodeError::UnknownVersion => write!(f, "The data is in an unknown format."),
+			DecodeError::Checksum => write!(f, "The checksum doesn't match."),
+			DecodeError::Rules => write!(f, "The rules are invalid."),
+			DecodeError::PlayerCount => write!(f, "The game can't have this many players."),
+			DecodeError::Turn => write!(f, "The turn is invalid."),
+			DecodeError::Target => write!(f, "The target is invalid."),
+			DecodeError::Phase => write!(f, "The phase doesn't match the boards."),
+			DecodeError::ShipOutOfBounds { player, index } => write!(f, "Ship {} of player {} is out of bounds.", index + 1, player.num()),
+			DecodeError::OverlappingShips { player, index, other } => write!(f, "Ships {} and {} of player {} overlap.", other + 1, index + 1, player.num()),
+			DecodeError::Placement { player, error } => write!(f, "The board of player {} is invalid: {error}", player.num()),
+			DecodeError::Hits { player } => write!(f, "The hits on the board of player {} are invalid.", player.num()),
+			DecodeError::Damage { player } => write!(f, "The armor on the board of player {} is invalid.", player.num()),
+			DecodeError::Charges { player } => write!(f, "Player {} has too many charges.", player.num()),
+			DecodeError::Teammate { player } => write!(f, "The team of player {} is invalid.", player.num()),
+			DecodeError::Objective { player } => write!(f, "The objective of player {} doesn't match the rules.", player.num()),
+			DecodeError::PendingShots { player } => write!(f, "The locked in shots of player {} are invalid.", player.num())
+		}
+	}
+}
+
+impl Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sealed_data_round_trips() {
+		let body = vec![0x12, 0x34, 0x56];
+		let sealed = seal(body.clone());
+
+		assert_eq!(sealed[0], Format::V2_TAG);
+		assert_eq!(sealed.len(), body.len() + 3);
+		assert_eq!(Format::detect(&sealed), Some(Format::V2));
+		assert_eq!(unseal(&sealed), Ok(&body[..]));
+		assert_eq!(unseal(&seal(Vec::new())), Ok(&[][..]));
+	}
+
+	#[test]
+	fn checksum_is_crc16_ccitt_false() {
+		assert_eq!(checksum(b"123456789"), 0x29B1);
+		assert_eq!(checksum(&[]), 0xFFFF);
+	}
+
+	#[test]
+	fn corrupted_data_is_rejected() {
+		let sealed = seal(vec![0x12, 0x34, 0x56]);
+
+		// Any flipped bit past the tag shows in the checksum
+		for index in 1..sealed.len() {
+			for bit in 0..8 {
+				let mut corrupted = sealed.clone();
+				corrupted[index] ^= 1 << bit;
+				assert_eq!(unseal(&corrupted), Err(DecodeError::Checksum), "byte {index}, bit {bit}");
+			}
+		}
+
+		let mut untagged = sealed.clone();
+		untagged[0] = 0;
+		assert_eq!(unseal(&untagged), Err(DecodeError::UnknownVersion));
+		assert_eq!(unseal(&sealed[..2]), Err(DecodeError::Length));
+	}
+
+	#[test]
+	fn bits_round_trip() {
+		let mut res = BitWriter::new();
+		res.write(0b101, 3);
+		res.write_bool(true);
+		res.write(0xABCD, 16);
+		let bytes = res.finish();
+		assert_eq!(bytes.len(), 3);
+
+		let mut data = BitReader::new(&bytes);
+		assert_eq!(data.read(3), Ok(0b101));
+		assert_eq!(data.read_bool(), Ok(true));
+		assert_eq!(data.finish(), Err(DecodeError::Length));
+		assert_eq!(data.read(16), Ok(0xABCD));
+		assert_eq!(data.finish(), Ok(()));
+		assert_eq!(data.read(8), Err(DecodeError::Length));
+	}
 }
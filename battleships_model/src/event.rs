@@ -0,0 +1,346 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::depth::Layer;
+use crate::game_state::{GameState, Ship, ShipInfo, ShipState, Turn, Vec2};
+use crate::phase::Phase;
+use crate::round::PendingShots;
+use crate::rules::GameRules;
+use crate::shot::ShotResult;
+use crate::weapon::Weapon;
+
+/// Something that happened during a game. Folding every event of a game onto the state it started from rebuilds it,
+/// see [`GameState::replay`]. Randomness like the layouts or where mines strike back is part of the events,
+/// so the same events always lead to the same state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameEvent {
+	/// A ship of the player was placed, or moved during the battle.
+	ShipPlaced { player: Turn, index: usize, state: ShipState },
+	/// A mine or decoy of the player was placed.
+	TrapPlaced { player: Turn, index: usize, pos: Vec2 },
+	HeadquartersPlaced { player: Turn, pos: Vec2 },
+	/// The secret flagship of the player was picked from the fleet.
+	FlagshipChosen { player: Turn, index: usize },
+	/// The player is done arranging their board.
+	PlacementConfirmed { player: Turn },
+	/// A charge of the weapon was used against the target. Strikes are followed by their shots.
+	WeaponUsed { player: Turn, target: Turn, weapon: Weapon, pos: Vec2 },
+	/// Shots were locked in, to land once every player did.
+	ShotsLockedIn { player: Turn, shots: PendingShots },
+	/// A tile was fired at in the layer, by a regular shot, a weapon or a depth charge.
+	ShotFired { shooter: Turn, target: Turn, tile: Vec2, layer: Layer },
+	/// A mine cost the player a tile of their own fleet.
+	Backfire { player: Turn, tile: Vec2, layer: Layer },
+	/// A ship tile was hit, including armored tiles that held.
+	/// Only tells what the shot or backfire before it did, so it changes nothing by itself.
+	Hit { target: Turn, tile: Vec2 },
+	/// A ship was sunk by the shot or backfire before. Changes nothing by itself.
+	Sunk { target: Turn, info: &'static ShipInfo },
+	/// The player is done firing for now, so their teammate fires next time.
+	TurnEnded { player: Turn },
+	/// The turn went to the player, who aims at the target.
+	TurnPassed { turn: Turn, target: Turn },
+	/// The current player aims at another opponent.
+	TargetChosen { target: Turn },
+	/// Every player had their turn in another round of a points race.
+	RoundEnded,
+	/// The game is over, won by the player unless it was a draw.
+	GameWon { winner: Option<Turn> }
+}
+
+/// A tile that was fired at, see [`GameState::shots`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shot {
+	pub shooter: Turn,
+	pub target: Turn,
+	pub tile: Vec2,
+	pub layer: Layer
+}
+
+/// Why an event can't be applied to a state, see [`GameState::apply`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventError {
+	/// The players or rules can't start a game, see [`GameState::new`].
+	Start,
+	/// The player isn't part of the game.
+	UnknownPlayer(Turn),
+	/// The player has no ship or trap with that index.
+	UnknownIndex { player: Turn, index: usize },
+	/// The tile, or a tile of the ship, isn't on the board.
+	OutOfBounds(Vec2),
+	/// The player has no charges of the weapon left.
+	NoCharges { player: Turn, weapon: Weapon }
+}
+
+// What applying an event did, as far as the reports need to know
+#[derive(Debug, Default)]
+pub(crate) struct Effect {
+	pub(crate) result: Option<ShotResult>,
+	/// Decoys that were exposed.
+	pub(crate) exposed: Vec<Vec2>
+}
+
+impl GameState {
+	/// Starts a game like [`GameState::new`], then applies the events of a previous one in order.
+	/// Given the whole log of that game, the result is the same state it ended up in.
+	pub fn replay(player_ids: &[u64], rules: GameRules, events: &[GameEvent]) -> Result<Self, EventError> {
+		let mut res = Self::new(player_ids, rules).ok_or(EventError::Start)?;
+		res.events.clear();
+
+		for event in events {
+			res.apply(event.clone())?;
+		}
+
+		Ok(res)
+	}
+
+	/// Applies an event and adds it to the log.
+	/// Events that name players, ships or tiles this game doesn't have are rejected, and so are weapons without charges.
+	/// Anything else is trusted to come from a game with the same players and rules.
+	pub fn apply(&mut self, event: GameEvent) -> Result<(), EventError> {
+		self.check_event(&event)?;
+		self.record(event);
+		Ok(())
+	}
+
+	/// Puts back a log that was kept elsewhere, like after decoding the state.
+	pub fn restore_events(&mut self, events: Vec<GameEvent>) {
+		self.events = events;
+	}
+
+	/// Everything that happened since the state was started or decoded, in order.
+	/// The log isn't part of the encoding, so it has to be taken and kept elsewhere to outlive a button.
+	pub fn events(&self) -> &[GameEvent] {
+		&self.events
+	}
+
+	/// Empties the log, returning what was in it.
+	pub fn take_events(&mut self) -> Vec<GameEvent> {
+		std::mem::take(&mut self.events)
+	}
+
+	/// Every tile fired at in the log, in order.
+	pub fn shots(&self) -> impl Iterator<Item = Shot> + '_ {
+		self.events.iter().filter_map(|e| match *e {
+			GameEvent::ShotFired { shooter, target, tile, layer } => Some(Shot { shooter, target, tile, layer }),
+			_ => None
+		})
+	}
+
+	/// Applies an event and logs it.
+	pub(crate) fn record(&mut self, event: GameEvent) -> Effect {
+		let effect = self.apply_event(&event);
+		self.events.push(event);
+		effect
+	}
+
+	/// Logs an event whose changes were already made.
+	pub(crate) fn log(&mut self, event: GameEvent) {
+		self.events.push(event);
+	}
+
+	/// Logs what a shot or backfire on the target's ship did.
+	pub(crate) fn log_outcome(&mut self, target: Turn, tile: Vec2, result: ShotResult) {
+		if matches!(result, ShotResult::Hit | ShotResult::Damaged | ShotResult::Sunk(_)) {
+			self.log(GameEvent::Hit { target, tile });
+		}
+
+		if let ShotResult::Sunk(info) = result {
+			self.log(GameEvent::Sunk { target, info });
+		}
+	}
+
+	// Everything applying the event relies on
+	fn check_event(&self, event: &GameEvent) -> Result<(), EventError> {
+		let player = |turn: Turn| self.players().get(turn.index()).ok_or(EventError::UnknownPlayer(turn));
+		let tile = |pos: Vec2| if self.rules().board_size.contains(pos) { Ok(()) } else { Err(EventError::OutOfBounds(pos)) };
+
+		match *event {
+			GameEvent::ShipPlaced { player: turn, index, state } => {
+				let info = player(turn)?.ships().get(index).ok_or(EventError::UnknownIndex { player: turn, index })?.info;
+				let ship = Ship::new(info, state, self.rules());
+				if let Some(pos) = ship.tiles().find(|&t| !self.rules().board_size.contains(t)) {
+					return Err(EventError::OutOfBounds(pos));
+				}
+			}
+			GameEvent::TrapPlaced { player: turn, index, pos } => {
+				player(turn)?.traps().get(index).ok_or(EventError::UnknownIndex { player: turn, index })?;
+				tile(pos)?;
+			}
+			GameEvent::HeadquartersPlaced { player: turn, pos } => {
+				player(turn)?;
+				tile(pos)?;
+			}
+			GameEvent::FlagshipChosen { player: turn, index } => {
+				player(turn)?.ships().get(index).ok_or(EventError::UnknownIndex { player: turn, index })?;
+			}
+			GameEvent::WeaponUsed { player: turn, target, weapon, pos } => {
+				if player(turn)?.charges(weapon) == 0 { return Err(EventError::NoCharges { player: turn, weapon }); }
+				player(target)?;
+				tile(pos)?;
+			}
+			GameEvent::ShotsLockedIn { player: turn, ref shots } => {
+				player(turn)?;
+				player(shots.target)?;
+				shots.tiles.iter().try_for_each(|&pos| tile(pos))?;
+			}
+			GameEvent::ShotFired { shooter, target, tile: pos, .. } => {
+				player(shooter)?;
+				player(target)?;
+				tile(pos)?;
+			}
+			GameEvent::Backfire { player: turn, tile: pos, .. } | GameEvent::Hit { target: turn, tile: pos } => {
+				player(turn)?;
+				tile(pos)?;
+			}
+			GameEvent::TurnPassed { turn, target } => {
+				player(turn)?;
+				player(target)?;
+			}
+			GameEvent::PlacementConfirmed { player: turn }
+			| GameEvent::Sunk { target: turn, .. }
+			| GameEvent::TurnEnded { player: turn }
+			| GameEvent::TargetChosen { target: turn }
+			| GameEvent::GameWon { winner: Some(turn) } => {
+				player(turn)?;
+			}
+			GameEvent::RoundEnded | GameEvent::GameWon { winner: None } => {}
+		}
+
+		Ok(())
+	}
+
+	fn apply_event(&mut self, event: &GameEvent) -> Effect {
+		let rules = self.rules().clone();
+
+		match *event {
+			GameEvent::ShipPlaced { player, index, state } => {
+				self.player_mut(player).set_ship(index, state);
+			}
+			GameEvent::TrapPlaced { player, index, pos } => {
+				self.player_mut(player).set_trap(index, pos);
+			}
+			GameEvent::HeadquartersPlaced { player, pos } => {
+				self.player_mut(player).set_headquarters(Some(pos));
+			}
+			GameEvent::FlagshipChosen { player, index } => {
+				self.player_mut(player).set_flagship(Some(index));
+			}
+			GameEvent::PlacementConfirmed { player } => {
+				self.set_phase(self.phase_after_placement(player));
+			}
+			GameEvent::WeaponUsed { player, target, weapon, pos } => {
+				self.player_mut(player).use_charge(weapon);
+
+				if weapon == Weapon::Sonar {
+					let area = weapon.area(pos, &rules);
+					let exposed = self.player_mut(target).expose_decoys(|pos| area.contains(&pos));
+					return Effect { result: None, exposed };
+				}
+			}
+			GameEvent::ShotsLockedIn { player, ref shots } => {
+				self.player_mut(player).set_pending(Some(shots.clone()));
+			}
+			GameEvent::ShotFired { shooter, target, tile, layer } => {
+				return self.fire_at(shooter, target, tile, layer);
+			}
+			GameEvent::Backfire { player, tile, layer } => {
+				let result = self.backfire_at(player, tile, layer);
+				return Effect { result: Some(result), exposed: Vec::new() };
+			}
+			GameEvent::TurnEnded { player } => {
+				let player = self.player_mut(player);
+				player.set_pending(None);
+				player.pass_relay();
+			}
+			GameEvent::TurnPassed { turn, target } => self.apply_turn(turn, target),
+			GameEvent::TargetChosen { target } => self.apply_turn(self.turn(), target),
+			GameEvent::RoundEnded => self.end_round(),
+			GameEvent::GameWon { winner } => self.set_phase(Phase::Finished { winner }),
+			GameEvent::Hit { .. } | GameEvent::Sunk { .. } => {}
+		}
+
+		Effect::default()
+	}
+}
+
+impl Display for EventError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			EventError::Start => write!(f, "The game can't be started with these players and rules."),
+			EventError::UnknownPlayer(turn) => write!(f, "Player {} isn't part of the game.", turn.num()),
+			EventError::UnknownIndex { player, index } => write!(f, "Player {} has nothing with index {index}.", player.num()),
+			EventError::OutOfBounds(pos) => write!(f, "Tile ({}, {}) is not on the board.", pos.x, pos.y),
+			EventError::NoCharges { player, weapon } => write!(f, "Player {} has no {} left.", player.num(), weapon.label())
+		}
+	}
+}
+
+impl Error for EventError {}
+
+#[cfg(test)]
+mod tests {
+	use crate::game_state::Rotation;
+	use crate::movement::ShipMove;
+	use crate::rules::{MovementRule, WeaponRule};
+
+	use super::*;
+
+	#[test]
+	fn replaying_the_log_rebuilds_the_game() {
+		let ids = [1, 2, 3];
+		let rules = GameRules { movement: MovementRule::Moving, ..Default::default() };
+		let mut state = GameState::new(&ids, rules.clone()).unwrap();
+
+		state.randomize_ships().unwrap();
+		while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+
+		// Everyone fires at the first open tile of their target, with the odd move and change of target in between
+		for step in 0..150 {
+			if state.is_over() { break; }
+			if step % 7 == 3 && state.move_ship(0, ShipMove::Down).is_ok() { continue; }
+			if step % 5 == 1 {
+				let target = state.opponents().last().unwrap();
+				state.set_target(target);
+			}
+
+			let tile: Vec<Vec2> = state.rules().board_size.tiles()
+				.filter(|&t| state.target().is_open(t, Layer::Surface, MovementRule::Moving))
+				.take(1)
+				.collect();
+			state.fire(&tile).unwrap();
+		}
+
+		assert!(state.shots().count() > 0);
+
+		let replayed = GameState::replay(&ids, rules, state.events()).unwrap();
+		assert_eq!(replayed.events(), state.events());
+		assert_eq!(format!("{replayed:?}"), format!("{state:?}"));
+	}
+
+	#[test]
+	fn broken_events_are_rejected() {
+		let rules = GameRules { weapons: WeaponRule::Arsenal, ..Default::default() };
+		let mut state = GameState::new(&[1, 2], rules).unwrap();
+		let logged = state.events().len();
+
+		let outside = Vec2::new(10, 0);
+		assert_eq!(state.apply(GameEvent::TurnEnded { player: Turn::new(3, 3).unwrap() }), Err(EventError::UnknownPlayer(Turn::new(3, 3).unwrap())));
+		assert_eq!(state.apply(GameEvent::FlagshipChosen { player: state.turn(), index: 5 }), Err(EventError::UnknownIndex { player: state.turn(), index: 5 }));
+		assert_eq!(state.apply(GameEvent::ShotFired { shooter: state.turn(), target: state.target_turn(), tile: outside, layer: Layer::Surface }), Err(EventError::OutOfBounds(outside)));
+
+		// A ship may not be moved off the board either
+		let ship = ShipState::new(Vec2::new(8, 0), Rotation::HORI);
+		assert!(matches!(state.apply(GameEvent::ShipPlaced { player: state.turn(), index: 0, state: ship }), Err(EventError::OutOfBounds(_))));
+		assert_eq!(state.events().len(), logged);
+
+		// Using up every charge leaves none for another event
+		let (player, target, weapon, pos) = (state.turn(), state.target_turn(), Weapon::Sonar, Vec2::new(0, 0));
+		while state.current().charges(weapon) > 0 {
+			state.apply(GameEvent::WeaponUsed { player, target, weapon, pos }).unwrap();
+		}
+		assert_eq!(state.apply(GameEvent::WeaponUsed { player, target, weapon, pos }), Err(EventError::NoCharges { player, weapon }));
+		assert_eq!(state.events().len(), logged + usize::from(weapon.starting_charges()));
+	}
+}
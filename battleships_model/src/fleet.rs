@@ -0,0 +1,135 @@
+use crate::encode::{BitReader, BitWriter, DecodeError};
+use crate::game_state::{BoardSize, ShipInfo};
+use crate::rules::PlacementRule;
+
+/// The list of ships every player places on their board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fleet(Vec<&'static ShipInfo>);
+
+impl Fleet {
+	pub const MAX_SHIPS: usize = 16;
+
+	/// The preset fleets, in the order they are encoded.
+	const PRESETS: [fn() -> Fleet; 4] = [Fleet::classic, Fleet::russian, Fleet::duel, Fleet::shapes];
+
+	// Fleets are encoded either as a preset index or as a list of ship classes
+	const PRESET_BITS: u8 = 3;
+	const COUNT_BITS: u8 = 4;
+	const CLASS_BITS: u8 = 4;
+
+	pub fn new(ships: Vec<&'static ShipInfo>) -> Option<Self> {
+		if ships.is_empty() || ships.len() > Self::MAX_SHIPS {
+			None
+		} else {
+			Some(Self(ships))
+		}
+	}
+
+	/// Carrier, Battleship, Cruiser, Submarine and Destroyer.
+	pub fn classic() -> Self {
+		Self(vec![
+			&ShipInfo::CARRIER,
+			&ShipInfo::BATTLESHIP,
+			&ShipInfo::CRUISER,
+			&ShipInfo::SUBMARINE,
+			&ShipInfo::DESTROYER,
+		])
+	}
+
+	/// One ship of 4 tiles, two of 3, three of 2 and four of 1.
+	pub fn russian() -> Self {
+		let mut ships = vec![&ShipInfo::BATTLESHIP];
+		ships.extend([&ShipInfo::CRUISER; 2]);
+		ships.extend([&ShipInfo::DESTROYER; 3]);
+		ships.extend([&ShipInfo::PATROL_BOAT; 4]);
+		Self(ships)
+	}
+
+	/// A short game with just three ships.
+	pub fn duel() -> Self {
+		Self(vec![
+			&ShipInfo::BATTLESHIP,
+			&ShipInfo::CRUISER,
+			&ShipInfo::DESTROYER,
+		])
+	}
+
+	/// A fleet mixing straight and L-, T- and plus-shaped ships.
+	pub fn shapes() -> Self {
+		Self(vec![
+			&ShipInfo::CARRIER,
+			&ShipInfo::FORTRESS,
+			&ShipInfo::FRIGATE,
+			&ShipInfo::CORVETTE,
+			&ShipInfo::DESTROYER,
+		])
+	}
+
+	pub fn ships(&self) -> &[&'static ShipInfo] {
+		&self.0
+	}
+
+	pub fn tile_count(&self) -> usize {
+		self.0.iter().map(|s| usize::from(s.tile_count())).sum()
+	}
+
+	/// Whether the fleet can reasonably be placed on a board of the given size.
+	/// Fleets covering more than half the board are rejected, since random
+	/// placement would have a hard time finding room for the last ships.
+	pub fn fits(&self, board_size: BoardSize, placement: PlacementRule) -> bool {
+		self.fits_around(board_size, placement, 0)
+	}
+
+	/// Like [`Fleet::fits`], but also leaves room for single-tile obstacles like traps and terrain.
+	pub fn fits_around(&self, board_size: BoardSize, placement: PlacementRule, obstacles: usize) -> bool {
+		let size = usize::from(board_size.get());
+		let spacing = usize::from(placement.spacing());
+
+		// The required spacing is split between neighbours, so every ship
+		// and the board itself effectively grow by half of it on each side.
+		let ship_area: usize = self.0.iter()
+			.map(|s| {
+				// Shaped ships get to count their gaps, since nothing else can fit in there anyway
+				let extent = s.extent();
+				if spacing == 0 { usize::from(s.tile_count()) }
+				else { (usize::from(extent.x) + spacing) * (usize::from(extent.y) + spacing) }
+			})
+			.sum::<usize>() + obstacles * (1 + spacing) * (1 + spacing);
+		let board_area = (size + spacing) * (size + spacing);
+
+		self.0.iter().all(|s| usize::from(s.extent().x.max(s.extent().y)) <= size) &&
+		ship_area * 2 <= board_area
+	}
+
+	pub(crate) fn write(&self, res: &mut BitWriter) {
+		if let Some(preset) = Self::PRESETS.iter().position(|p| p() == *self) {
+			res.write_bool(true);
+			res.write(preset as u64, Self::PRESET_BITS);
+		} else {
+			res.write_bool(false);
+			res.write(self.0.len() as u64 - 1, Self::COUNT_BITS);
+			for ship in &self.0 {
+				res.write(ship.index as u64, Self::CLASS_BITS);
+			}
+		}
+	}
+
+	pub(crate) fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		if data.read_bool()? {
+			let preset = Self::PRESETS.get(data.read(Self::PRESET_BITS)? as usize).ok_or(DecodeError::Rules)?;
+			Ok(preset())
+		} else {
+			let count = data.read(Self::COUNT_BITS)? as usize + 1;
+			let ships = (0..count)
+				.map(|_| ShipInfo::ALL.get(data.read(Self::CLASS_BITS)? as usize).copied().ok_or(DecodeError::Rules))
+				.collect::<Result<Vec<_>, _>>()?;
+			Self::new(ships).ok_or(DecodeError::Rules)
+		}
+	}
+}
+
+impl Default for Fleet {
+	fn default() -> Self {
+		Self::classic()
+	}
+}
@@ -1,44 +1,105 @@
-use std::fmt::Debug;
-use std::mem::size_of;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
-use rand::distributions::{Uniform, Standard};
+use rand::distributions::Uniform;
+use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
 
-use crate::encode::Encode;
-
-pub const GRID_SIZE: u8 = 10;
+use crate::depth::Layer;
+use crate::encode::{seal, unseal, BitReader, BitWriter, DecodeError, Encode, Format};
+use crate::event::GameEvent;
+use crate::grid::Grid;
+use crate::phase::{Phase, PhaseError};
+use crate::round::PendingShots;
+use crate::rules::{GameMode, GameRules, LayerRule, MovementRule, TopologyRule, TurnRule, VictoryRule, WeaponRule, ROUND_BITS};
+use crate::terrain::{Terrain, TerrainKind};
+use crate::trap::{Trap, TrapKind};
+use crate::weapon::Weapon;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct GameState {
-	pub player_1: PlayerState,
-	pub player_2: PlayerState,
-	turn: Turn
+	players: Vec<PlayerState>,
+	rules: GameRules,
+	turn: Turn,
+	/// The opponent the current player is aiming at.
+	target: Turn,
+	/// The rounds every player has taken their turn in so far. Only counted in a points race.
+	round: u8,
+	phase: Phase,
+	/// Everything that happened since the state was started or decoded, see [`GameState::events`].
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub(crate) events: Vec<GameEvent>
 }
 
+/// A board and the players it belongs to.
+/// In team battles, both teammates share one board.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlayerState {
 	pub user_id: u64,
+	pub teammate_id: Option<u64>,
 	pub hits: HitMatrix,
-	ships: [ShipState; ShipInfo::COUNT],
+	/// Misses that a ship has moved onto since. The opponents still see them as misses.
+	stale: HitMatrix,
+	/// The hits armored tiles took without being destroyed.
+	damage: DamageMatrix,
+	/// Hits on submerged ships. Depth charges that miss leave nothing to see, so they aren't kept.
+	depth_hits: HitMatrix,
+	ships: Vec<Ship>,
+	traps: Vec<Trap>,
+	/// Whether the teammate fires next, rather than the first player.
+	relay: bool,
+	/// The charges left for every special weapon, indexed by [`Weapon::index`].
+	#[cfg_attr(feature = "serde", serde(with = "crate::serialize::charges"))]
+	charges: [u8; Weapon::COUNT],
+	/// The shots locked in for the current round, if everyone fires at once.
+	pending: Option<PendingShots>,
+	/// The index of the secret flagship in the fleet, if there is one.
+	flagship: Option<usize>,
+	/// The hidden tile that loses the game once hit, if there is one.
+	headquarters: Option<Vec2>,
+	/// One for every tile of the enemy ships this board sank.
+	points: u8
 }
 
+/// The width and height of the square board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardSize(u8);
+
+/// Hits are stored with a fixed row stride of [`BoardSize::MAX`],
+/// so the matrix does not need to know the actual board size.
 #[derive(Debug, Clone)]
-pub struct HitMatrix(u128);
+pub struct HitMatrix([u128; 2]);
 
+/// A hit counter for every tile, with the same layout as [`HitMatrix`].
 #[derive(Debug, Clone)]
+pub struct DamageMatrix([u8; DamageMatrix::LEN]);
+
+#[derive(Debug, Clone, Copy)]
 pub struct Ship {
 	pub info: &'static ShipInfo,
-	pub state: ShipState
+	pub state: ShipState,
+	/// The board the ship is on, which decides how its tiles are laid out.
+	grid: Grid,
+	layer: Layer
 }
 
-#[derive(Clone, Copy)]
-pub struct ShipState(u8);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShipState {
+	pos: Vec2,
+	rot: Rotation
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ShipInfo {
 	pub label: &'static str,
 	pub index: usize,
-	pub len: u8
+	/// The tiles of the ship, relative to the top-left corner when unrotated.
+	pub shape: &'static [Vec2],
+	/// The extra hits every tile takes before it is destroyed, if ships are armored.
+	pub armor: u8
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,12 +108,37 @@ pub struct Vec2 {
 	pub y: u8
 }
 
+/// One of the four quarter turns, optionally mirrored before turning.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Rotation(u8);
 
+/// The number of a player, or of a team in team battles, starting at 1.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Turn(u8);
 
+/// Why a ship couldn't be placed where a player wanted it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+	/// The player's fleet has no ship with that index.
+	UnknownShip,
+	/// The ship would stick out of the board.
+	OutOfBounds,
+	/// The ship would overlap another ship, given by its index in the fleet.
+	Overlap { index: usize, info: &'static ShipInfo },
+	/// The ship would be closer to another ship than the placement rule allows.
+	TooClose { index: usize, info: &'static ShipInfo },
+	/// The tile is already taken by a mine or decoy.
+	Trap(TrapKind),
+	/// Ships and traps can't be placed on islands or reefs.
+	Terrain(TerrainKind),
+	/// The ship can't face that way on this board.
+	Rotation,
+	/// The tile is taken by the headquarters, or too close to them.
+	Headquarters,
+	/// The board can't be arranged anymore.
+	Phase(PhaseError)
+}
+
 impl Vec2 {
 	pub const fn new(x: u8, y: u8) -> Self {
 		Vec2 { x, y }
@@ -60,364 +146,1787 @@ impl Vec2 {
 }
 
 impl GameState {
-	pub fn new(player_1_id: u64, player_2_id: u64) -> Self {
-		GameState {
-			player_1: PlayerState::new(player_1_id),
-			player_2: PlayerState::new(player_2_id),
-			turn: Turn(1)
+	pub const MIN_PLAYERS: usize = 2;
+	pub const MAX_PLAYERS: usize = 4;
+	pub const TEAM_SIZE: usize = 2;
+
+	/// Starts a game between the given players, who take their turns in this order.
+	/// In team battles, there have to be exactly four players, and the first two play against the last two.
+	/// Every ship of the fleet has to fit on the grid, so hex boards only take straight ships.
+	pub fn new(player_ids: &[u64], rules: GameRules) -> Option<Self> {
+		if player_ids.len() < Self::MIN_PLAYERS || player_ids.len() > Self::MAX_PLAYERS {
+			return None;
+		}
+
+		if rules.fleet.ships().iter().any(|&info| rules.grid().orientations(info).is_empty()) {
+			return None;
+		}
+
+		let players = match rules.mode {
+			GameMode::FreeForAll => player_ids.iter()
+				.map(|&id| PlayerState::new(id, &rules))
+				.collect(),
+			GameMode::Teams => {
+				if player_ids.len() != Self::TEAM_SIZE * 2 { return None; }
+
+				player_ids.chunks(Self::TEAM_SIZE)
+					.map(|ids| PlayerState { teammate_id: Some(ids[1]), ..PlayerState::new(ids[0], &rules) })
+					.collect()
+			}
+		};
+
+		let mut res = GameState {
+			players,
+			rules,
+			turn: Turn(1),
+			target: Turn(2),
+			round: 0,
+			phase: Phase::AwaitingPlacement(Turn(1)),
+			events: Vec::new()
+		};
+
+		// The random layouts are the first thing that happened
+		for index in 0..res.players.len() {
+			res.log_layout(Turn(index as u8 + 1));
 		}
+
+		Some(res)
+	}
+
+	pub fn rules(&self) -> &GameRules {
+		&self.rules
+	}
+
+	pub fn players(&self) -> &[PlayerState] {
+		&self.players
+	}
+
+	pub fn players_mut(&mut self) -> &mut [PlayerState] {
+		&mut self.players
+	}
+
+	pub fn player(&self, turn: Turn) -> &PlayerState {
+		&self.players[turn.index()]
 	}
 
 	pub fn current(&self) -> &PlayerState {
-		match self.turn.0 {
-			1 => &self.player_1,
-			2 => &self.player_2,
-			_ => panic!("invalid turn state")
-		}
+		self.player(self.turn)
+	}
+
+	pub(crate) fn player_mut(&mut self, turn: Turn) -> &mut PlayerState {
+		&mut self.players[turn.index()]
 	}
 
 	pub fn current_mut(&mut self) -> &mut PlayerState {
-		match self.turn.0 {
-			1 => &mut self.player_1,
-			2 => &mut self.player_2,
-			_ => panic!("invalid turn state")
-		}
+		&mut self.players[self.turn.index()]
 	}
 
 	pub fn target(&self) -> &PlayerState {
-		// This returns the *inactive* turn
-		match self.turn.0 {
-			1 => &self.player_2,
-			2 => &self.player_1,
-			_ => panic!("invalid turn state")
-		}
+		self.player(self.target)
 	}
 
 	pub fn target_mut(&mut self) -> &mut PlayerState {
-		// This returns the *inactive* turn
-		match self.turn.0 {
-			1 => &mut self.player_2,
-			2 => &mut self.player_1,
-			_ => panic!("invalid turn state")
-		}
+		&mut self.players[self.target.index()]
 	}
 
 	pub fn turns(&self) -> (&PlayerState, &PlayerState) {
-		match self.turn.0 {
-			1 => (&self.player_1, &self.player_2),
-			2 => (&self.player_2, &self.player_1),
-			_ => panic!("invalid turn state")
-		}
+		(self.current(), self.target())
 	}
 
-	pub fn swap_turn(&mut self) {
-		self.turn.0 = match self.turn.0 {
-			1 => 2,
-			2 => 1,
-			_ => panic!("invalid turn state")
-		};
+	pub fn turn(&self) -> Turn {
+		self.turn
+	}
+
+	pub fn target_turn(&self) -> Turn {
+		self.target
 	}
 
 	pub fn turn_num(&self) -> u8 {
 		self.turn.num()
 	}
-}
 
-// This is just cursed.
-fn has_overlaps(ships: &[ShipState; ShipInfo::COUNT], index: usize) -> bool {
-	let ship = Ship { info: &ShipInfo::ALL[index], state: ships[index] };
-	let (b_l, b_r) = ship.bounds();
+	/// The rounds of a points race that were completed so far, where every player still in the game had a turn.
+	pub fn round(&self) -> u8 {
+		self.round
+	}
 
-	ships.iter()
-		.zip(ShipInfo::ALL)
-		.take(index)
-		.map(|t| {
-			// Map each state into its bounds
-			let (&state, info) = t;
-			Ship { info, state }.bounds()
-		})
-		.any(move |(a_l, a_r)| {
-			// Check if any of the positions mapped above
-			// are in range of the current ship
-			b_l.x <= a_r.x && a_l.x <= b_r.x && // Overlap on X
-			b_l.y <= a_r.y && a_l.y <= b_r.y // Overlap on Y
-		})
-}
+	pub fn phase(&self) -> Phase {
+		self.phase
+	}
 
-impl PlayerState {
-	pub fn new(user_id: u64) -> Self {
-		Self {
-			user_id,
-			ships: Self::new_random_ships(),
-			hits: HitMatrix::new()
+	pub(crate) fn set_phase(&mut self, phase: Phase) {
+		self.phase = phase;
+	}
+
+	/// The players the current player may fire at.
+	pub fn opponents(&self) -> impl Iterator<Item = Turn> + '_ {
+		self.opponents_of(self.turn)
+	}
+
+	/// The players still in the game other than `turn`, in turn order after them.
+	pub(crate) fn opponents_of(&self, turn: Turn) -> impl Iterator<Item = Turn> + Clone + '_ {
+		self.turns_after(turn).filter(move |&t| t != turn)
+	}
+
+	/// Aims at another player, if they are still in the game.
+	pub fn set_target(&mut self, target: Turn) -> bool {
+		if self.opponents().any(|t| t == target) {
+			self.record(GameEvent::TargetChosen { target });
+			true
+		} else {
+			false
 		}
 	}
 
-	pub fn randomize_ships(&mut self) {
-		self.ships = Self::new_random_ships();
+	/// Hands the turn to a player directly, aiming at the given target.
+	pub(crate) fn set_turn(&mut self, turn: Turn, target: Turn) {
+		self.record(GameEvent::TurnPassed { turn, target });
 	}
 
-	pub fn ships(&self) -> [Ship; ShipInfo::COUNT] {
-		[
-			Ship { info: &ShipInfo::CARRIER, state: self.ships[0] },
-			Ship { info: &ShipInfo::BATTLESHIP, state: self.ships[1] },
-			Ship { info: &ShipInfo::CRUISER, state: self.ships[2] },
-			Ship { info: &ShipInfo::SUBMARINE, state: self.ships[3] },
-			Ship { info: &ShipInfo::DESTROYER, state: self.ships[4] },
-		]
+	/// Where a turn passed or a new target ends up, see [`GameEvent::TurnPassed`].
+	pub(crate) fn apply_turn(&mut self, turn: Turn, target: Turn) {
+		self.turn = turn;
+		self.target = target;
 	}
 
-	pub fn overlap(&self, pos: Vec2) -> Option<Ship> {
-		self.ships().iter().find(|&s| {
-			let (l, r) = s.bounds();
-			pos.x >= l.x && pos.y >= l.y &&
-			pos.x <= r.x && pos.y <= r.y
-		}).cloned()
+	/// Counts another round, see [`GameEvent::RoundEnded`].
+	pub(crate) fn end_round(&mut self) {
+		self.round = self.round.saturating_add(1);
 	}
 
-	pub fn is_sunk(&self, ship: &Ship) -> bool {
-		let mut res = true;
+	/// Passes the turn to the next player that is still in the game.
+	/// They aim at the player after them by default.
+	pub fn next_turn(&mut self) {
+		let next = self.turns_after(self.turn).next();
+		if let Some(turn) = next {
+			// Coming back around to an earlier player starts a new round
+			let counted = matches!(self.rules.victory, VictoryRule::Points { .. }) && self.phase == Phase::Battle;
+			if counted && turn.index() <= self.turn.index() {
+				self.record(GameEvent::RoundEnded);
+			}
 
-		for tile in ship.tiles() {
-			if tile.x > GRID_SIZE || tile.y > GRID_SIZE { break; }
-			res &= self.hits.get(tile);
+			let target = self.default_target(turn);
+			self.set_turn(turn, target);
+		} else {
+			self.retarget();
 		}
+	}
 
-		res
+	/// Aims at the next opponent after the current player, if the target is out of the game.
+	pub fn retarget(&mut self) {
+		let target = self.default_target(self.turn);
+		if target != self.target {
+			self.record(GameEvent::TargetChosen { target });
+		}
 	}
 
-	pub fn are_all_ships_sunk(&self) -> bool {
-		self.ships().iter().all(|s| self.is_sunk(s))
+	// The target if it is still an opponent of `turn`, otherwise the next opponent after them
+	fn default_target(&self, turn: Turn) -> Turn {
+		let mut opponents = self.opponents_of(turn);
+		if opponents.clone().any(|t| t == self.target) {
+			self.target
+		} else {
+			opponents.next().unwrap_or(self.target)
+		}
 	}
 
-	fn new_random_ships() -> [ShipState; ShipInfo::COUNT] {
-		let mut rng = thread_rng();
-		let mut ships = [ShipState(0); ShipInfo::COUNT];
+	/// Moves a ship of the current player before the battle, see [`PlayerState::place_ship`].
+	pub fn place_ship(&mut self, index: usize, pos: Vec2, rot: Rotation) -> Result<(), PlacementError> {
+		self.phase.check_placement().map_err(PlacementError::Phase)?;
 
-		for i in 0..ShipInfo::COUNT {
-			let info = ShipInfo::ALL[i];
-			loop {
-				ships[i] = ShipState::random(&mut rng, info);
-				if !has_overlaps(&ships, i) { break; }
-			}
-		}
+		let rules = self.rules.clone();
+		self.current_mut().place_ship(index, pos, rot, &rules)?;
 
-		ships
+		let state = self.current().ships[index].state;
+		self.log(GameEvent::ShipPlaced { player: self.turn, index, state });
+		Ok(())
 	}
-}
 
-impl HitMatrix {
-	pub const ALL: Self = Self(u128::MAX);
+	/// Moves a trap of the current player before the battle, see [`PlayerState::place_trap`].
+	pub fn place_trap(&mut self, index: usize, pos: Vec2) -> Result<(), PlacementError> {
+		self.phase.check_placement().map_err(PlacementError::Phase)?;
 
-	pub const fn new() -> Self {
-		HitMatrix(0)
+		let rules = self.rules.clone();
+		self.current_mut().place_trap(index, pos, &rules)?;
+		self.log(GameEvent::TrapPlaced { player: self.turn, index, pos });
+		Ok(())
 	}
 
-	pub fn get(&self, slot: Vec2) -> bool {
-		(self.0 & Self::get_mask(slot)) != 0
+	/// Moves the headquarters of the current player before the battle, see [`PlayerState::place_headquarters`].
+	pub fn place_headquarters(&mut self, pos: Vec2) -> Result<(), PlacementError> {
+		self.phase.check_placement().map_err(PlacementError::Phase)?;
+
+		let rules = self.rules.clone();
+		self.current_mut().place_headquarters(pos, &rules)?;
+		self.log(GameEvent::HeadquartersPlaced { player: self.turn, pos });
+		Ok(())
 	}
 
-	pub fn set(&mut self, slot: Vec2) {
-		self.0 |= Self::get_mask(slot);
+	/// Randomly places everything of the current player again, see [`PlayerState::randomize_ships`].
+	pub fn randomize_ships(&mut self) -> Result<(), PhaseError> {
+		self.phase.check_placement()?;
+
+		let rules = self.rules.clone();
+		self.current_mut().randomize_ships(&rules);
+		self.log_layout(self.turn);
+		Ok(())
 	}
 
-	pub fn unset(&mut self, slot: Vec2) {
-		self.0 &= !Self::get_mask(slot);
+	// Logs where everything of the player is now
+	fn log_layout(&mut self, player: Turn) {
+		let state = &self.players[player.index()];
+		let ships = state.ships.iter().enumerate()
+			.map(|(index, ship)| GameEvent::ShipPlaced { player, index, state: ship.state });
+		let traps = state.traps.iter().enumerate()
+			.map(|(index, trap)| GameEvent::TrapPlaced { player, index, pos: trap.pos });
+		let headquarters = state.headquarters.map(|pos| GameEvent::HeadquartersPlaced { player, pos });
+		let flagship = state.flagship.map(|index| GameEvent::FlagshipChosen { player, index });
+
+		let events: Vec<GameEvent> = ships.chain(traps).chain(headquarters).chain(flagship).collect();
+		self.events.extend(events);
 	}
 
-	fn get_mask(slot: Vec2) -> u128 {
-		1u128 << (slot.x + slot.y * GRID_SIZE)
+	// Players still in the game, in turn order starting after `turn`.
+	fn turns_after(&self, turn: Turn) -> impl Iterator<Item = Turn> + Clone + '_ {
+		let count = self.players.len();
+		(1..=count)
+			.map(move |offset| Turn(((turn.index() + offset) % count + 1) as u8))
+			.filter(move |&t| !self.player(t).is_defeated())
 	}
 }
 
-impl Default for HitMatrix {
-	fn default() -> Self {
-		Self::new()
+fn has_overlaps(ships: &[Ship], index: usize, rules: &GameRules) -> bool {
+	let ship = &ships[index];
+	let spacing = rules.placement.spacing();
+
+	// Check if any of the ships placed before are in range of the current ship.
+	ships[..index].iter().any(|other| is_in_range(ship, other, spacing, rules))
+}
+
+fn is_on_board(ship: &Ship, board_size: BoardSize) -> bool {
+	ship.tiles().all(|t| board_size.contains(t))
+}
+
+fn is_on_terrain(ship: &Ship, terrain: &Terrain) -> bool {
+	ship.tiles().any(|t| terrain.kind_at(t).is_some())
+}
+
+fn check_trap_spacing(ships: &[Ship], pos: Vec2, rules: &GameRules) -> Result<(), PlacementError> {
+	let spacing = rules.placement.spacing();
+	for (index, ship) in ships.iter().enumerate() {
+		for tile in ship.tiles() {
+			if tile == pos {
+				return Err(PlacementError::Overlap { index, info: ship.info });
+			}
+
+			if rules.grid().distance(tile, pos) <= spacing {
+				return Err(PlacementError::TooClose { index, info: ship.info });
+			}
+		}
 	}
+
+	Ok(())
 }
 
-impl Ship {
-	pub fn bounds(&self) -> (Vec2, Vec2) {
-		let len = self.info.len;
-		let rot = self.state.rotation();
-		let tl_pos = self.state.position();
-		let mut br_pos = tl_pos;
-		if rot == Rotation::HORI { br_pos.x += len; } else { br_pos.y += len; }
+fn check_headquarters(ships: &[Ship], traps: &[Trap], pos: Vec2, rules: &GameRules) -> Result<(), PlacementError> {
+	if !rules.board_size.contains(pos) { return Err(PlacementError::OutOfBounds); }
 
-		(tl_pos, br_pos)
+	if let Some(kind) = rules.terrain.layout(rules.board_size).kind_at(pos) {
+		return Err(PlacementError::Terrain(kind));
 	}
 
-	pub fn tiles(&self) -> impl Iterator<Item = Vec2> {
-		let len = self.info.len;
-		let rot = self.state.rotation();
-		let pos = self.state.position();
-
-		(0..len).map(move |i| {
-			let mut t_pos = pos;
-			if rot == Rotation::HORI { t_pos.x += i; } else { t_pos.y += i; }
-			t_pos
-		})
+	if let Some(trap) = traps.iter().find(|t| t.pos == pos) {
+		return Err(PlacementError::Trap(trap.kind));
 	}
+
+	check_trap_spacing(ships, pos, rules)
 }
 
-impl ShipState {
-	pub fn new(pos: Vec2, rot: Rotation) -> Self {
-		const LEN: u8 = GRID_SIZE - 1;
+fn is_in_range(a: &Ship, b: &Ship, spacing: u8, rules: &GameRules) -> bool {
+	let in_range = |a: Vec2, b: Vec2| rules.grid().distance(a, b) <= spacing;
 
-		assert!(pos.x < GRID_SIZE);
-		assert!(pos.y < GRID_SIZE);
+	// Bounds only work out on flat square boards
+	if rules.topology != TopologyRule::Flat {
+		return a.tiles().any(|a| b.tiles().any(|b| in_range(a, b)));
+	}
 
-		if rot == Rotation::HORI {
-			assert!(pos.x < LEN);
-			Self(pos.x * LEN + pos.y)
-		} else {
-			assert!(pos.y < LEN);
-			Self((pos.x + pos.y * LEN) | (1 << 7))
-		}
+	let (a_l, a_r) = a.bounds();
+	let (b_l, b_r) = b.bounds();
+
+	// Quickly rule out ships that aren't even close.
+	// The required spacing is added to the far side of each range.
+	let bounds_overlap =
+		b_l.x <= a_r.x + spacing && a_l.x <= b_r.x + spacing && // Overlap on X
+		b_l.y <= a_r.y + spacing && a_l.y <= b_r.y + spacing; // Overlap on Y
+
+	// Shaped ships may still have a gap where their bounds overlap
+	bounds_overlap && a.tiles().any(|a| b.tiles().any(|b| in_range(a, b)))
+}
+
+impl PlayerState {
+	pub fn new(user_id: u64, rules: &GameRules) -> Self {
+		let mut res = Self {
+			user_id,
+			teammate_id: None,
+			ships: Vec::new(),
+			traps: Vec::new(),
+			hits: HitMatrix::new(),
+			stale: HitMatrix::new(),
+			damage: DamageMatrix::new(),
+			depth_hits: HitMatrix::new(),
+			relay: false,
+			charges: match rules.weapons {
+				WeaponRule::Standard => [0; Weapon::COUNT],
+				WeaponRule::Arsenal => Weapon::ALL.map(Weapon::starting_charges)
+			},
+			pending: None,
+			flagship: None,
+			headquarters: None,
+			points: 0
+		};
+
+		res.randomize_ships(rules);
+		res
 	}
 
-	pub fn random(rng: &mut impl Rng, info: &ShipInfo) -> Self {
-		let short = rng.sample(Uniform::new(0, GRID_SIZE - info.len));
-		let full = rng.sample(Uniform::new(0, GRID_SIZE));
-		if rng.sample(Standard) {
-			ShipState::new(Vec2::new(short, full), Rotation::HORI)
-		} else {
-			ShipState::new(Vec2::new(full, short), Rotation::VERT)
+	pub fn charges(&self, weapon: Weapon) -> u8 {
+		self.charges[weapon.index()]
+	}
+
+	pub(crate) fn use_charge(&mut self, weapon: Weapon) {
+		self.charges[weapon.index()] -= 1;
+	}
+
+	pub fn points(&self) -> u8 {
+		self.points
+	}
+
+	pub(crate) fn add_points(&mut self, points: u8) {
+		self.points = self.points.saturating_add(points);
+	}
+
+	pub fn flagship(&self) -> Option<&Ship> {
+		self.flagship.map(|index| &self.ships[index])
+	}
+
+	pub fn is_flagship(&self, index: usize) -> bool {
+		self.flagship == Some(index)
+	}
+
+	pub fn headquarters(&self) -> Option<Vec2> {
+		self.headquarters
+	}
+
+	/// Everyone playing on this board.
+	pub fn members(&self) -> impl Iterator<Item = u64> {
+		std::iter::once(self.user_id).chain(self.teammate_id)
+	}
+
+	pub fn is_member(&self, user_id: u64) -> bool {
+		self.members().any(|id| id == user_id)
+	}
+
+	/// The player who fires the next shots for this board.
+	pub fn shooter_id(&self) -> u64 {
+		match self.teammate_id {
+			Some(teammate_id) if self.relay => teammate_id,
+			_ => self.user_id
 		}
 	}
 
-	pub fn position(self) -> Vec2 {
-		const LEN: u8 = GRID_SIZE - 1;
-		let val = self.0 & 0b0111_1111;
-		let x = val / LEN;
-		let y = val % LEN;
-		if self.rotation() == Rotation::HORI {
-			Vec2::new(x, y)
-		} else {
-			Vec2::new(y, x)
+	/// Marks every decoy matching the condition as exposed, returning the ones that weren't before.
+	pub(crate) fn expose_decoys(&mut self, condition: impl Fn(Vec2) -> bool) -> Vec<Vec2> {
+		self.traps.iter_mut()
+			.filter(|t| t.kind == TrapKind::Decoy && !t.exposed && condition(t.pos))
+			.map(|t| {
+				t.exposed = true;
+				t.pos
+			})
+			.collect()
+	}
+
+	pub fn pending(&self) -> Option<&PendingShots> {
+		self.pending.as_ref()
+	}
+
+	pub(crate) fn set_pending(&mut self, pending: Option<PendingShots>) {
+		self.pending = pending;
+	}
+
+	/// Lets the teammate fire next time, if there is one.
+	pub(crate) fn pass_relay(&mut self) {
+		self.relay = !self.relay && self.teammate_id.is_some();
+	}
+
+	/// Randomly places the fleet, as well as the mines, decoys and headquarters.
+	/// If the rules call for a flagship, a new one is picked as well.
+	pub fn randomize_ships(&mut self, rules: &GameRules) {
+		(self.ships, self.traps, self.headquarters) = Self::new_random_layout(rules);
+		self.flagship = (rules.victory == VictoryRule::Flagship)
+			.then(|| thread_rng().gen_range(0..self.ships.len()));
+	}
+
+	pub fn ships(&self) -> &[Ship] {
+		&self.ships
+	}
+
+	pub fn traps(&self) -> &[Trap] {
+		&self.traps
+	}
+
+	pub fn trap_at(&self, pos: Vec2) -> Option<&Trap> {
+		self.traps.iter().find(|t| t.pos == pos)
+	}
+
+	/// Moves the trap at `index`, if the new position is valid under the rules.
+	/// Traps keep the same distance to ships that ships keep to each other.
+	pub fn place_trap(&mut self, index: usize, pos: Vec2, rules: &GameRules) -> Result<(), PlacementError> {
+		if index >= self.traps.len() { return Err(PlacementError::UnknownShip); }
+		self.check_trap(index, pos, rules)?;
+		check_trap_spacing(&self.ships, pos, rules)?;
+
+		self.traps[index].pos = pos;
+		Ok(())
+	}
+
+	// Ships that move later only keep off traps, so the spacing to them is checked on its own
+	fn check_trap(&self, index: usize, pos: Vec2, rules: &GameRules) -> Result<(), PlacementError> {
+		if !rules.board_size.contains(pos) { return Err(PlacementError::OutOfBounds); }
+
+		if let Some(kind) = rules.terrain.layout(rules.board_size).kind_at(pos) {
+			return Err(PlacementError::Terrain(kind));
+		}
+
+		if let Some(other) = self.traps.iter().enumerate().find(|&(i, t)| i != index && t.pos == pos) {
+			return Err(PlacementError::Trap(other.1.kind));
 		}
+
+		if self.headquarters == Some(pos) { return Err(PlacementError::Headquarters); }
+
+		Ok(())
 	}
 
-	pub fn rotation(self) -> Rotation {
-		Rotation(self.0 >> 7)
+	/// Moves the headquarters, if the game has them. They keep their distance to ships just like traps,
+	/// so marking the surroundings of a sunk ship never captures them.
+	pub fn place_headquarters(&mut self, pos: Vec2, rules: &GameRules) -> Result<(), PlacementError> {
+		if self.headquarters.is_none() { return Err(PlacementError::UnknownShip); }
+		check_headquarters(&self.ships, &self.traps, pos, rules)?;
+
+		self.headquarters = Some(pos);
+		Ok(())
 	}
-}
 
-impl Debug for ShipState {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.debug_struct("ShipState")
-			.field("position", &self.position())
-			.field("rotation", &self.rotation())
-			.finish()
+	/// Moves the ship at `index` of the fleet, if the new position is valid under the rules.
+	/// On error, the ship stays where it was.
+	pub fn place_ship(&mut self, index: usize, pos: Vec2, rot: Rotation, rules: &GameRules) -> Result<(), PlacementError> {
+		let mut ship = *self.ships.get(index).ok_or(PlacementError::UnknownShip)?;
+		ship.state = ShipState::new(pos, rot);
+
+		self.check_ship(index, &ship, rules)?;
+		self.set_ship(index, ship.state);
+		Ok(())
 	}
-}
 
-impl ShipInfo {
-	pub const COUNT: usize = 5;
+	// Whether the ship could take the place of the one at `index`
+	fn check_ship(&self, index: usize, ship: &Ship, rules: &GameRules) -> Result<(), PlacementError> {
+		let (pos, rot) = (ship.state.pos, ship.state.rot);
+		if !rules.grid().is_valid_rotation(rot) {
+			return Err(PlacementError::Rotation);
+		}
 
-	const CARRIER: ShipInfo = ShipInfo::new("Carrier", 0, 5);
-	const BATTLESHIP: ShipInfo = ShipInfo::new("Battleship", 1, 4);
-	const CRUISER: ShipInfo = ShipInfo::new("Cruiser", 2, 3);
-	const SUBMARINE: ShipInfo = ShipInfo::new("Submarine", 3, 3);
-	const DESTROYER: ShipInfo = ShipInfo::new("Destroyer", 4, 2);
+		if !rules.board_size.contains(pos) || !is_on_board(ship, rules.board_size) {
+			return Err(PlacementError::OutOfBounds);
+		}
 
-	pub const ALL: [&ShipInfo; ShipInfo::COUNT] = [
-		&ShipInfo::CARRIER,
-		&ShipInfo::BATTLESHIP,
-		&ShipInfo::CRUISER,
-		&ShipInfo::SUBMARINE,
-		&ShipInfo::DESTROYER,
-	];
+		let terrain = rules.terrain.layout(rules.board_size);
+		if let Some(kind) = ship.tiles().find_map(|t| terrain.kind_at(t)) {
+			return Err(PlacementError::Terrain(kind));
+		}
+
+		if let Some(trap) = self.traps.iter().find(|t| ship.tiles().any(|s| s == t.pos)) {
+			return Err(PlacementError::Trap(trap.kind));
+		}
+
+		let spacing = rules.placement.spacing();
+		if let Some(pos) = self.headquarters {
+			if ship.tiles().any(|t| rules.grid().distance(t, pos) <= spacing) {
+				return Err(PlacementError::Headquarters);
+			}
+		}
+
+		let others = || self.ships.iter().enumerate().filter(|&(i, _)| i != index);
+
+		if let Some((index, other)) = others().find(|(_, other)| is_in_range(ship, other, 0, rules)) {
+			return Err(PlacementError::Overlap { index, info: other.info });
+		}
+
+		if let Some((index, other)) = others().find(|(_, other)| is_in_range(ship, other, spacing, rules)) {
+			return Err(PlacementError::TooClose { index, info: other.info });
+		}
 
-	const fn new(label: &'static str, index: usize, len: u8) -> Self {
-		Self { label, index, len }
+		Ok(())
 	}
-}
 
-impl Turn {
-	pub fn new(num: u8) -> Option<Turn> {
-		if num == 0 || num == 1 {
-			Some(Turn(num))
-		} else {
-			None
+	/// Moves a ship without checking the rules. Misses under its new tiles become stale,
+	/// while the stale misses it leaves behind are plain misses again.
+	pub(crate) fn set_ship(&mut self, index: usize, state: ShipState) {
+		let old = self.ships[index];
+		for tile in old.tiles() {
+			self.stale.unset(tile);
+		}
+
+		self.ships[index].state = state;
+
+		// Misses on the surface don't matter to submerged ships
+		let ship = self.ships[index];
+		for tile in ship.tiles().filter(|_| ship.layer == Layer::Surface) {
+			if self.hits.get(tile) { self.stale.set(tile); }
 		}
 	}
 
-	pub fn num(self) -> u8 {
-		self.0
+	pub(crate) fn set_trap(&mut self, index: usize, pos: Vec2) {
+		self.traps[index].pos = pos;
 	}
-}
 
-impl Rotation {
-	pub const HORI: Self = Self(0);
-	pub const VERT: Self = Self(1);
-}
+	pub(crate) fn set_headquarters(&mut self, pos: Option<Vec2>) {
+		self.headquarters = pos;
+	}
 
-impl Encode for GameState {
-	type Out = Vec<u8>;
-	type In = [u8];
+	pub(crate) fn set_flagship(&mut self, index: Option<usize>) {
+		self.flagship = index;
+	}
 
-	fn encode(&self) -> Self::Out {
-		let mut res = Vec::with_capacity(59);
+	pub fn overlap(&self, pos: Vec2) -> Option<Ship> {
+		self.ships.iter().find(|&s| s.tiles().any(|t| t == pos)).copied()
+	}
 
-		res.extend(self.player_1.encode());
-		res.extend(self.player_2.encode());
-		res.push(self.turn.0);
+	/// Whether the tile holds a miss that a ship has moved onto since.
+	pub fn is_stale(&self, pos: Vec2) -> bool {
+		self.stale.get(pos)
+	}
+
+	/// Whether a ship or trap in the layer, or the headquarters, were hit on this tile.
+	pub fn is_hit(&self, pos: Vec2, layer: Layer) -> bool {
+		match layer {
+			Layer::Surface => {
+				let ship = self.overlap(pos).filter(|s| s.layer == Layer::Surface);
+				let target = ship.is_some() || self.trap_at(pos).is_some() || self.headquarters == Some(pos);
+				self.hits.get(pos) && !self.stale.get(pos) && target
+			}
+			Layer::Depth => self.depth_hits.get(pos)
+		}
+	}
+
+	/// Whether the tile may be fired at in the layer.
+	/// Once ships can move, a miss might not be one anymore, so only hits are ruled out.
+	/// Below the surface, only hits are known to begin with.
+	pub fn is_open(&self, pos: Vec2, layer: Layer, movement: MovementRule) -> bool {
+		match (layer, movement) {
+			(Layer::Surface, MovementRule::Fixed) => !self.hits.get(pos),
+			_ => !self.is_hit(pos, layer)
+		}
+	}
 
-		debug_assert_eq!(res.len(), 59);
+	pub fn is_sunk(&self, ship: &Ship) -> bool {
+		let mut res = true;
+
+		for tile in ship.tiles() {
+			res &= match ship.layer {
+				Layer::Surface => self.hits.get(tile) && !self.stale.get(tile),
+				Layer::Depth => self.depth_hits.get(tile)
+			};
+		}
 
 		res
 	}
 
-	fn try_decode(data: &Self::In) -> Option<Self> {
-		if data.len() != 59 { return None; }
+	/// Whether none of the ship's tiles were hit, so it may still move.
+	pub fn is_intact(&self, ship: &Ship) -> bool {
+		match ship.layer {
+			Layer::Surface => ship.tiles().all(|t| (!self.hits.get(t) || self.stale.get(t)) && self.damage.get(t) == 0),
+			Layer::Depth => ship.tiles().all(|t| !self.depth_hits.get(t))
+		}
+	}
+
+	/// The hits the armor of the tile took so far.
+	pub fn damage(&self, pos: Vec2) -> u8 {
+		self.damage.get(pos)
+	}
 
-		let player_1 = PlayerState::try_decode(&data[0..29]).unwrap();
-		let player_2 = PlayerState::try_decode(&data[29..58]).unwrap();
-		let turn = Turn(data[58]);
+	/// Lets the armor of the tile take a hit, if it has any left. Returns whether it did.
+	/// The tile stays open, so it can be fired at again.
+	pub(crate) fn absorb_hit(&mut self, pos: Vec2, armor: u8) -> bool {
+		let damage = self.damage.get(pos);
+		if damage >= armor { return false; }
 
-		Some(Self {
-			player_1,
-			player_2,
-			turn
-		})
+		self.damage.set(pos, damage + 1);
+		self.hits.unset(pos);
+		self.stale.unset(pos);
+		true
 	}
-}
 
-impl Encode for PlayerState {
-	type Out = Vec<u8>;
-	type In = [u8];
+	pub fn are_all_ships_sunk(&self) -> bool {
+		self.ships.iter().all(|s| self.is_sunk(s))
+	}
 
-	fn encode(&self) -> Self::Out {
-		let mut res = Vec::with_capacity(29);
+	/// Marks a tile in the layer as hit. If it was stale, whatever is on it now gets hit.
+	/// Any armor on the tile is gone along with it.
+	pub(crate) fn hit(&mut self, pos: Vec2, layer: Layer) {
+		match layer {
+			Layer::Surface => {
+				self.hits.set(pos);
+				self.stale.unset(pos);
+				self.damage.set(pos, 0);
+			}
+			Layer::Depth => {
+				if self.overlap(pos).is_some_and(|s| s.layer == Layer::Depth) {
+					self.depth_hits.set(pos);
+				}
+			}
+		}
+	}
+
+	/// Marks every tile around the ship as hit on the surface.
+	/// Only meaningful if ships can't touch, since the tiles are known to be empty then.
+	/// That goes for submerged ships as well, which keep their distance to the ones above.
+	pub fn mark_surroundings(&mut self, ship: &Ship, rules: &GameRules) {
+		for tile in ship.tiles() {
+			for pos in rules.grid().surroundings(tile) {
+				self.hits.set(pos);
+			}
+		}
+	}
+
+	fn new_random_layout(rules: &GameRules) -> (Vec<Ship>, Vec<Trap>, Option<Vec2>) {
+		// The ships may leave no room for the traps, in which case they get placed again too.
+		loop {
+			let ships = Self::new_random_ships(rules);
+			let Some(traps) = Self::new_random_traps(&ships, rules) else { continue; };
 
-		res.extend(self.user_id.to_be_bytes());
-		res.extend(self.hits.0.to_be_bytes());
-		res.extend(self.ships.map(|s| s.0));
+			if rules.victory != VictoryRule::Headquarters {
+				return (ships, traps, None);
+			}
 
-		debug_assert_eq!(res.len(), 29);
+			// The headquarters take a free tile just like the traps
+			let free: Vec<Vec2> = rules.board_size.tiles()
+				.filter(|&pos| check_headquarters(&ships, &traps, pos, rules).is_ok())
+				.collect();
 
-		res
+			if let Some(&pos) = free.choose(&mut thread_rng()) {
+				return (ships, traps, Some(pos));
+			}
+		}
+	}
+
+	fn new_random_traps(ships: &[Ship], rules: &GameRules) -> Option<Vec<Trap>> {
+		const MAX_ATTEMPTS: usize = 100;
+
+		let mut rng = thread_rng();
+		let mut traps: Vec<Trap> = Vec::with_capacity(rules.traps.layout().len());
+		let coord = Uniform::new(0, rules.board_size.get());
+		let terrain = rules.terrain.layout(rules.board_size);
+
+		for &kind in rules.traps.layout() {
+			// Any free tile will do, so just draw until one fits
+			let pos = (0..MAX_ATTEMPTS)
+				.map(|_| Vec2::new(rng.sample(coord), rng.sample(coord)))
+				.find(|&pos| {
+					traps.iter().all(|t| t.pos != pos) &&
+					terrain.kind_at(pos).is_none() &&
+					check_trap_spacing(ships, pos, rules).is_ok()
+				})?;
+
+			traps.push(Trap { kind, pos, exposed: false });
+		}
+
+		Some(traps)
 	}
 
-	fn try_decode(data: &Self::In) -> Option<Self> {
-		if data.len() != 29 { return None; }
+	fn new_random_ships(rules: &GameRules) -> Vec<Ship> {
+		// Ships placed early may leave no room for the later ones.
+		// If that happens, we just start over with a fresh board.
+		const MAX_ATTEMPTS: usize = 100;
 
-		const U64_SIZE: usize = size_of::<u64>();
-		const U128_SIZE: usize = size_of::<u128>();
+		let mut rng = thread_rng();
+		let terrain = rules.terrain.layout(rules.board_size);
 
-		const HITS_START: usize = U64_SIZE;
-		const SHIPS_START: usize = HITS_START + U128_SIZE;
+		'restart: loop {
+			let mut ships: Vec<Ship> = Vec::new();
 
-		let user_id = u64::from_be_bytes(data[..HITS_START].try_into().unwrap());
-		let hits = HitMatrix(u128::from_be_bytes(data[HITS_START..SHIPS_START].try_into().unwrap()));
-		let ships: [u8; ShipInfo::COUNT] = data[SHIPS_START..].try_into().unwrap();
-		let ships = ships.map(|b| ShipState(b));
+			for &info in rules.fleet.ships() {
+				ships.push(Ship::new(info, ShipState::random(&mut rng, info, rules), rules));
+				let index = ships.len() - 1;
 
-		Some(Self {
-			user_id,
-			hits,
-			ships
-		})
+				let mut attempts = 0;
+				// Ships placed anywhere may still stick out of hex boards
+				while !is_on_board(&ships[index], rules.board_size) || has_overlaps(&ships, index, rules) || is_on_terrain(&ships[index], &terrain) {
+					attempts += 1;
+					if attempts >= MAX_ATTEMPTS { continue 'restart; }
+
+					ships[index].state = ShipState::random(&mut rng, info, rules);
+				}
+			}
+
+			return ships;
+		}
+	}
+}
+
+impl BoardSize {
+	pub const MIN: u8 = 6;
+	pub const MAX: u8 = 16;
+	pub const DEFAULT: Self = Self(10);
+
+	pub const fn new(size: u8) -> Option<Self> {
+		if size >= Self::MIN && size <= Self::MAX {
+			Some(Self(size))
+		} else {
+			None
+		}
+	}
+
+	pub const fn get(self) -> u8 {
+		self.0
+	}
+
+	pub fn contains(self, pos: Vec2) -> bool {
+		pos.x < self.0 && pos.y < self.0
+	}
+
+	/// Iterates all tiles of the board, row by row.
+	pub fn tiles(self) -> impl Iterator<Item = Vec2> {
+		let size = self.0;
+		(0..size).flat_map(move |y| (0..size).map(move |x| Vec2::new(x, y)))
+	}
+}
+
+impl Default for BoardSize {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+impl HitMatrix {
+	pub const ALL: Self = Self([u128::MAX; 2]);
+
+	pub const fn new() -> Self {
+		HitMatrix([0; 2])
+	}
+
+	pub fn get(&self, slot: Vec2) -> bool {
+		let (index, mask) = Self::get_mask(slot);
+		(self.0[index] & mask) != 0
+	}
+
+	pub fn set(&mut self, slot: Vec2) {
+		let (index, mask) = Self::get_mask(slot);
+		self.0[index] |= mask;
+	}
+
+	pub fn unset(&mut self, slot: Vec2) {
+		let (index, mask) = Self::get_mask(slot);
+		self.0[index] &= !mask;
+	}
+
+	fn get_mask(slot: Vec2) -> (usize, u128) {
+		let bit = usize::from(slot.x) + usize::from(slot.y) * usize::from(BoardSize::MAX);
+		(bit / 128, 1u128 << (bit % 128))
+	}
+}
+
+impl Default for HitMatrix {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl DamageMatrix {
+	const LEN: usize = BoardSize::MAX as usize * BoardSize::MAX as usize;
+
+	pub const fn new() -> Self {
+		DamageMatrix([0; Self::LEN])
+	}
+
+	pub fn get(&self, slot: Vec2) -> u8 {
+		self.0[Self::get_index(slot)]
+	}
+
+	pub fn set(&mut self, slot: Vec2, value: u8) {
+		self.0[Self::get_index(slot)] = value;
+	}
+
+	fn get_index(slot: Vec2) -> usize {
+		usize::from(slot.x) + usize::from(slot.y) * usize::from(BoardSize::MAX)
+	}
+}
+
+impl Default for DamageMatrix {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Ship {
+	pub fn new(info: &'static ShipInfo, state: ShipState, rules: &GameRules) -> Self {
+		Self { info, state, grid: rules.grid(), layer: rules.layers.layer(info) }
+	}
+
+	pub fn layer(&self) -> Layer {
+		self.layer
+	}
+
+	/// The top-left and bottom-right corner of the tiles.
+	/// Only meaningful on flat square boards, where ships can't cross an edge.
+	pub fn bounds(&self) -> (Vec2, Vec2) {
+		let tl_pos = self.state.position();
+		let mut br_pos = tl_pos;
+		for tile in self.tiles() {
+			br_pos.x = br_pos.x.max(tile.x);
+			br_pos.y = br_pos.y.max(tile.y);
+		}
+
+		(tl_pos, br_pos)
+	}
+
+	pub fn tiles(&self) -> impl Iterator<Item = Vec2> {
+		self.grid.ship_tiles(self.state.position(), self.state.rotation(), self.info.shape)
+	}
+}
+
+impl ShipState {
+	pub const fn new(pos: Vec2, rot: Rotation) -> Self {
+		Self { pos, rot }
+	}
+
+	pub fn random(rng: &mut impl Rng, info: &'static ShipInfo, rules: &GameRules) -> Self {
+		let size = rules.board_size.get();
+		let orientations = rules.grid().orientations(info);
+		let rot = orientations[rng.sample(Uniform::new(0, orientations.len()))];
+
+		// Find how far the ship extends with this rotation.
+		// Elsewhere, ships may start anywhere and are checked once placed.
+		let extent = match rules.topology {
+			TopologyRule::Flat => Ship::new(info, ShipState::new(Vec2::new(0, 0), rot), rules).bounds().1,
+			TopologyRule::Wrapping | TopologyRule::Hex => Vec2::new(0, 0)
+		};
+		let x = rng.sample(Uniform::new(0, size - extent.x));
+		let y = rng.sample(Uniform::new(0, size - extent.y));
+		ShipState::new(Vec2::new(x, y), rot)
+	}
+
+	pub fn position(self) -> Vec2 {
+		self.pos
+	}
+
+	pub fn rotation(self) -> Rotation {
+		self.rot
+	}
+}
+
+impl ShipInfo {
+	pub const COUNT: usize = 9;
+
+	pub const CARRIER: ShipInfo = ShipInfo::new("Carrier", 0, &line::<5>()).armored(1);
+	pub const BATTLESHIP: ShipInfo = ShipInfo::new("Battleship", 1, &line::<4>()).armored(1);
+	pub const CRUISER: ShipInfo = ShipInfo::new("Cruiser", 2, &line::<3>());
+	pub const SUBMARINE: ShipInfo = ShipInfo::new("Submarine", 3, &line::<3>());
+	pub const DESTROYER: ShipInfo = ShipInfo::new("Destroyer", 4, &line::<2>());
+	pub const PATROL_BOAT: ShipInfo = ShipInfo::new("Patrol Boat", 5, &line::<1>());
+
+	/// L-shaped
+	pub const FRIGATE: ShipInfo = ShipInfo::new("Frigate", 6, &[
+		Vec2::new(0, 0),
+		Vec2::new(0, 1),
+		Vec2::new(0, 2), Vec2::new(1, 2),
+	]);
+
+	/// T-shaped
+	pub const CORVETTE: ShipInfo = ShipInfo::new("Corvette", 7, &[
+		Vec2::new(0, 0), Vec2::new(1, 0), Vec2::new(2, 0),
+		                 Vec2::new(1, 1),
+	]);
+
+	/// Plus-shaped
+	pub const FORTRESS: ShipInfo = ShipInfo::new("Fortress", 8, &[
+		                 Vec2::new(1, 0),
+		Vec2::new(0, 1), Vec2::new(1, 1), Vec2::new(2, 1),
+		                 Vec2::new(1, 2),
+	]);
+
+	/// Every known ship class, indexed by [`ShipInfo::index`].
+	pub const ALL: [&ShipInfo; ShipInfo::COUNT] = [
+		&ShipInfo::CARRIER,
+		&ShipInfo::BATTLESHIP,
+		&ShipInfo::CRUISER,
+		&ShipInfo::SUBMARINE,
+		&ShipInfo::DESTROYER,
+		&ShipInfo::PATROL_BOAT,
+		&ShipInfo::FRIGATE,
+		&ShipInfo::CORVETTE,
+		&ShipInfo::FORTRESS,
+	];
+
+	const fn new(label: &'static str, index: usize, shape: &'static [Vec2]) -> Self {
+		Self { label, index, shape, armor: 0 }
+	}
+
+	const fn armored(mut self, armor: u8) -> Self {
+		self.armor = armor;
+		self
+	}
+
+	/// The amount of tiles of the ship.
+	pub fn tile_count(&self) -> u8 {
+		self.shape.len() as u8
+	}
+
+	/// The width and height of the unrotated ship.
+	pub fn extent(&self) -> Vec2 {
+		let mut res = Vec2::new(0, 0);
+		for tile in self.shape {
+			res.x = res.x.max(tile.x + 1);
+			res.y = res.y.max(tile.y + 1);
+		}
+
+		res
+	}
+
+	/// Whether the ship is a single straight row of tiles.
+	pub fn is_straight(&self) -> bool {
+		self.shape.iter().enumerate().all(|(i, t)| usize::from(t.x) == i && t.y == 0)
+	}
+
+	/// The rotations that result in different layouts on square boards, so symmetric ships aren't favored.
+	/// See [`Grid::orientations`] for other boards.
+	pub fn orientations(&'static self) -> Vec<Rotation> {
+		let mut res: Vec<Rotation> = Vec::new();
+		let mut layouts: Vec<Vec<Vec2>> = Vec::new();
+
+		for rot in Rotation::ALL {
+			let mut layout: Vec<Vec2> = rot.apply(self.shape).collect();
+			layout.sort_by_key(|t| (t.y, t.x));
+
+			if !layouts.contains(&layout) {
+				layouts.push(layout);
+				res.push(rot);
+			}
+		}
+
+		res
+	}
+}
+
+// A straight ship of the given length
+const fn line<const N: usize>() -> [Vec2; N] {
+	let mut res = [Vec2::new(0, 0); N];
+	let mut i = 0;
+	while i < N {
+		res[i] = Vec2::new(i as u8, 0);
+		i += 1;
+	}
+
+	res
+}
+
+impl Turn {
+	pub fn new(num: u8, player_count: usize) -> Option<Turn> {
+		if num >= 1 && usize::from(num) <= player_count {
+			Some(Turn(num))
+		} else {
+			None
+		}
+	}
+
+	pub fn num(self) -> u8 {
+		self.0
+	}
+
+	pub fn index(self) -> usize {
+		usize::from(self.0 - 1)
+	}
+}
+
+impl Rotation {
+	pub const HORI: Self = Self(0);
+	pub const VERT: Self = Self(1);
+
+	pub const ALL: [Self; 8] = [
+		Self(0), Self(1), Self(2), Self(3),
+		Self(4), Self(5), Self(6), Self(7),
+	];
+
+	const MIRRORED: u8 = 4;
+
+	pub const fn new(quarter_turns: u8, mirrored: bool) -> Self {
+		Self((quarter_turns % 4) | if mirrored { Self::MIRRORED } else { 0 })
+	}
+
+	/// The amount of clockwise quarter turns.
+	pub fn quarter_turns(self) -> u8 {
+		self.0 % 4
+	}
+
+	/// Whether the shape is mirrored left to right before turning.
+	pub fn is_mirrored(self) -> bool {
+		self.0 & Self::MIRRORED != 0
+	}
+
+	/// Rotates the tiles of a shape and moves them back so the top-left corner is at the origin.
+	pub fn apply(self, shape: &'static [Vec2]) -> impl Iterator<Item = Vec2> {
+		let turns = self.quarter_turns();
+		let mirrored = self.is_mirrored();
+
+		let transform = move |t: &Vec2| {
+			let (mut x, mut y) = (t.x as i8, t.y as i8);
+			if mirrored { x = -x; }
+			for _ in 0..turns { (x, y) = (-y, x); }
+			(x, y)
+		};
+
+		let min_x = shape.iter().map(transform).map(|(x, _)| x).min().unwrap_or(0);
+		let min_y = shape.iter().map(transform).map(|(_, y)| y).min().unwrap_or(0);
+
+		shape.iter()
+			.map(transform)
+			.map(move |(x, y)| Vec2::new((x - min_x) as u8, (y - min_y) as u8))
+	}
+}
+
+impl Display for PlacementError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PlacementError::UnknownShip => write!(f, "There is no such ship."),
+			PlacementError::OutOfBounds => write!(f, "The ship doesn't fit on the board there."),
+			PlacementError::Overlap { info, .. } => write!(f, "That would overlap the {}.", info.label),
+			PlacementError::TooClose { info, .. } => write!(f, "That would touch the {}.", info.label),
+			PlacementError::Trap(kind) => write!(f, "That tile is taken by a {}.", kind.label()),
+			PlacementError::Terrain(kind) => write!(f, "That would run aground on a {}.", kind.label()),
+			PlacementError::Rotation => write!(f, "Ships can't face that way on this board."),
+			PlacementError::Headquarters => write!(f, "That would be too close to the headquarters."),
+			PlacementError::Phase(err) => err.fmt(f)
+		}
+	}
+}
+
+impl Error for PlacementError {}
+
+// Submerged ships can't be hit on the surface, so they can't go stale either
+fn surface_tiles(ships: &[Ship]) -> impl Iterator<Item = Vec2> + '_ {
+	ships.iter().filter(|s| s.layer == Layer::Surface).flat_map(|s| s.tiles())
+}
+
+// Coordinates are always written with enough bits for the largest board.
+const COORD_BITS: u8 = 4;
+const ROTATION_BITS: u8 = 3;
+const PLAYER_BITS: u8 = 2;
+const CHARGE_BITS: u8 = 2;
+const ARMOR_BITS: u8 = 1;
+/// Enough for a salvo from a full [`Fleet`](crate::fleet::Fleet).
+const PENDING_BITS: u8 = 5;
+const FLAGSHIP_BITS: u8 = 4;
+const POINTS_BITS: u8 = 8;
+const PHASE_BITS: u8 = 2;
+/// A user id, the hits as a 10x10 bit mask and a byte for every ship.
+const V1_PLAYER_LEN: usize = 29;
+
+impl Encode for GameState {
+	type Out = Vec<u8>;
+	type In = [u8];
+
+	fn encode(&self) -> Self::Out {
+		let mut res = BitWriter::new();
+
+		self.rules.write(&mut res);
+		res.write((self.players.len() - GameState::MIN_PLAYERS) as u64, PLAYER_BITS);
+		res.write(self.turn.index() as u64, PLAYER_BITS);
+		res.write(self.target.index() as u64, PLAYER_BITS);
+		if let VictoryRule::Points { .. } = self.rules.victory {
+			res.write(self.round.into(), ROUND_BITS);
+		}
+
+		// The player arranging their board has the turn, and the winner follows from the boards
+		let phase = match self.phase {
+			Phase::AwaitingPlacement(_) => 0,
+			Phase::Battle => 1,
+			Phase::Finished { .. } => 2
+		};
+		res.write(phase, PHASE_BITS);
+
+		for player in &self.players {
+			player.write(&mut res, &self.rules);
+		}
+
+		seal(res.finish())
+	}
+
+	fn try_decode(data: &Self::In) -> Result<Self, DecodeError> {
+		match Format::detect(data).ok_or(DecodeError::UnknownVersion)? {
+			Format::V1 => Self::try_decode_v1(data, false),
+			Format::V2 => Self::try_decode_v2(unseal(data)?)
+		}
+	}
+}
+
+impl GameState {
+	/// Decodes a state in the first version of the format, see [`Format::V1`].
+	/// It didn't keep track of the phase, so `battle` tells whether both boards were arranged already.
+	/// Once a shot was fired, that's certain either way.
+	pub fn try_decode_v1(data: &[u8], battle: bool) -> Result<Self, DecodeError> {
+		if data.len() != Format::V1_LEN { return Err(DecodeError::Length); }
+
+		// There was only the classic game back then
+		let rules = GameRules::default();
+		let players = data[..Format::V1_LEN - 1]
+			.chunks(V1_PLAYER_LEN)
+			.enumerate()
+			.map(|(index, p)| PlayerState::read_v1(p, &rules, Turn(index as u8 + 1)))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let turn = Turn::new(data[Format::V1_LEN - 1], players.len()).ok_or(DecodeError::Turn)?;
+		let target = Turn(3 - turn.num());
+		let battle = battle || players.iter().any(|p| rules.board_size.tiles().any(|t| p.hits.get(t)));
+
+		let mut res = Self {
+			players,
+			rules,
+			turn,
+			target,
+			round: 0,
+			phase: if battle { Phase::Battle } else { Phase::AwaitingPlacement(turn) },
+			events: Vec::new()
+		};
+
+		// Telling whether the game is over needs sound boards
+		res.validate_players()?;
+		if battle && res.is_over() {
+			res.phase = Phase::Finished { winner: res.winner_turn() };
+		}
+
+		res.validate()?;
+		Ok(res)
+	}
+
+	fn try_decode_v2(data: &[u8]) -> Result<Self, DecodeError> {
+		let mut data = BitReader::new(data);
+
+		let rules = GameRules::read(&mut data)?;
+		let count = data.read(PLAYER_BITS)? as usize + GameState::MIN_PLAYERS;
+		if count > GameState::MAX_PLAYERS { return Err(DecodeError::PlayerCount); }
+
+		let turn = Turn::new(data.read(PLAYER_BITS)? as u8 + 1, count).ok_or(DecodeError::Turn)?;
+		let target = Turn::new(data.read(PLAYER_BITS)? as u8 + 1, count).ok_or(DecodeError::Target)?;
+
+		let round = match rules.victory {
+			VictoryRule::Points { .. } => data.read(ROUND_BITS)? as u8,
+			_ => 0
+		};
+
+		let phase = data.read(PHASE_BITS)?;
+
+		let players = (0..count)
+			.map(|index| PlayerState::read(&mut data, &rules, Turn(index as u8 + 1)))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		data.finish()?;
+
+		let mut res = Self {
+			players,
+			rules,
+			turn,
+			target,
+			round,
+			phase: match phase {
+				0 => Phase::AwaitingPlacement(turn),
+				1 => Phase::Battle,
+				2 => Phase::Finished { winner: None },
+				_ => return Err(DecodeError::Phase)
+			},
+			events: Vec::new()
+		};
+
+		// The winner isn't encoded, and telling it needs sound boards
+		res.validate_players()?;
+		if let Phase::Finished { .. } = res.phase {
+			res.phase = Phase::Finished { winner: res.winner_turn() };
+		}
+
+		res.validate()?;
+		Ok(res)
+	}
+
+	/// Puts a state back together that was taken apart some other way than [`Encode`], laying out the ships by the rules.
+	#[cfg(feature = "serde")]
+	pub(crate) fn from_parts(mut players: Vec<PlayerState>, rules: GameRules, turn: Turn, target: Turn, round: u8, phase: Phase) -> Result<Self, DecodeError> {
+		for ship in players.iter_mut().flat_map(|p| &mut p.ships) {
+			*ship = Ship::new(ship.info, ship.state, &rules);
+		}
+
+		let res = Self { players, rules, turn, target, round, phase, events: Vec::new() };
+		res.validate()?;
+		Ok(res)
+	}
+
+	/// Checks every invariant the actions keep up, like ships staying on the board and apart,
+	/// the turn and target being players that are still in the game, and the phase matching the boards.
+	/// Decoding checks this too, so a corrupted or crafted id is rejected rather than causing a panic later.
+	pub fn validate(&self) -> Result<(), DecodeError> {
+		self.validate_players()?;
+
+		let count = self.players.len();
+		if self.turn.index() >= count { return Err(DecodeError::Turn); }
+		if self.target.index() >= count || self.target == self.turn { return Err(DecodeError::Target); }
+
+		let shooting = self.rules.turns == TurnRule::Simultaneous;
+		for (index, player) in self.players.iter().enumerate() {
+			let Some(pending) = &player.pending else { continue; };
+			let player = Turn(index as u8 + 1);
+			if !shooting || pending.target.index() >= count || pending.target == player {
+				return Err(DecodeError::PendingShots { player });
+			}
+		}
+
+		// The game ends once the last round is reached
+		let last_round = match self.rules.victory {
+			VictoryRule::Points { rounds } => rounds,
+			_ => 0
+		};
+		if self.round > last_round { return Err(DecodeError::Turn); }
+
+		match self.phase {
+			Phase::AwaitingPlacement(player) => {
+				if player != self.turn { return Err(DecodeError::Turn); }
+				if self.players.iter().any(|p| p.pending.is_some()) { return Err(DecodeError::Phase); }
+			}
+			Phase::Battle => {
+				if self.is_over() { return Err(DecodeError::Phase); }
+				if self.current().is_defeated() { return Err(DecodeError::Turn); }
+			}
+			Phase::Finished { winner } => {
+				if !self.is_over() || winner != self.winner_turn() { return Err(DecodeError::Phase); }
+			}
+		}
+
+		Ok(())
+	}
+
+	// Everything that only depends on the rules and the boards themselves
+	fn validate_players(&self) -> Result<(), DecodeError> {
+		let count = self.players.len();
+		let expected = match self.rules.mode {
+			GameMode::FreeForAll => Self::MIN_PLAYERS..=Self::MAX_PLAYERS,
+			GameMode::Teams => 2..=2
+		};
+		if !expected.contains(&count) { return Err(DecodeError::PlayerCount); }
+
+		if self.rules.fleet.ships().iter().any(|&info| self.rules.grid().orientations(info).is_empty()) {
+			return Err(DecodeError::Rules);
+		}
+		if let VictoryRule::Points { rounds } = self.rules.victory {
+			if !(1..=VictoryRule::MAX_ROUNDS).contains(&rounds) { return Err(DecodeError::Rules); }
+		}
+
+		for (index, player) in self.players.iter().enumerate() {
+			player.validate(Turn(index as u8 + 1), &self.rules)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl PlayerState {
+	fn read_v1(data: &[u8], rules: &GameRules, player: Turn) -> Result<Self, DecodeError> {
+		let user_id = u64::from_be_bytes(data[..8].try_into().map_err(|_| DecodeError::Length)?);
+		let mask = u128::from_be_bytes(data[8..24].try_into().map_err(|_| DecodeError::Length)?);
+
+		let mut hits = HitMatrix::new();
+		for tile in rules.board_size.tiles() {
+			let size = rules.board_size.get();
+			if mask & (1 << (tile.x + tile.y * size)) != 0 { hits.set(tile); }
+		}
+
+		// Positions were packed as `x * 9 + y`, or as `x + y * 9` with the top bit set for vertical ships.
+		// Some positions share a byte, which always came back as the same one of them
+		let mut ships = Vec::with_capacity(rules.fleet.ships().len());
+		for (index, (&info, &byte)) in rules.fleet.ships().iter().zip(&data[24..]).enumerate() {
+			let (a, b) = ((byte & 0x7F) / 9, (byte & 0x7F) % 9);
+			let state = match byte & 0x80 {
+				0 => ShipState::new(Vec2::new(a, b), Rotation::HORI),
+				_ => ShipState::new(Vec2::new(b, a), Rotation::VERT)
+			};
+
+			let ship = Ship::new(info, state, rules);
+			if !is_on_board(&ship, rules.board_size) { return Err(DecodeError::ShipOutOfBounds { player, index }); }
+			ships.push(ship);
+		}
+
+		Ok(Self { ships, hits, ..Self::new(user_id, rules) })
+	}
+
+	fn write(&self, res: &mut BitWriter, rules: &GameRules) {
+		res.write(self.user_id, 64);
+
+		if rules.mode == GameMode::Teams {
+			res.write(self.teammate_id.unwrap_or_default(), 64);
+			res.write_bool(self.relay);
+		}
+
+		if rules.weapons == WeaponRule::Arsenal {
+			for charges in self.charges {
+				res.write(charges.into(), CHARGE_BITS);
+			}
+		}
+
+		for tile in rules.board_size.tiles() {
+			res.write_bool(self.hits.get(tile));
+		}
+
+		for ship in &self.ships {
+			let state = ship.state;
+			res.write(state.pos.x.into(), COORD_BITS);
+			res.write(state.pos.y.into(), COORD_BITS);
+			res.write(state.rot.0.into(), ROTATION_BITS);
+		}
+
+		// Only ship tiles can be stale. Every one of them gets a bit, so the length doesn't change as ships move
+		if rules.movement == MovementRule::Moving {
+			for tile in surface_tiles(&self.ships) {
+				res.write_bool(self.stale.get(tile));
+			}
+		}
+
+		// Only armored tiles that weren't destroyed yet can be damaged
+		for ship in &self.ships {
+			if rules.armor.armor(ship.info) == 0 { continue; }
+
+			for tile in ship.tiles().filter(|&t| !self.hits.get(t) || self.stale.get(t)) {
+				res.write(self.damage.get(tile).into(), ARMOR_BITS);
+			}
+		}
+
+		// Depth charges only leave a mark on submerged ships
+		if rules.layers == LayerRule::Submerged {
+			for tile in self.ships.iter().filter(|s| s.layer == Layer::Depth).flat_map(|s| s.tiles()) {
+				res.write_bool(self.depth_hits.get(tile));
+			}
+		}
+
+		for trap in &self.traps {
+			res.write(trap.pos.x.into(), COORD_BITS);
+			res.write(trap.pos.y.into(), COORD_BITS);
+			res.write_bool(trap.exposed);
+		}
+
+		match rules.victory {
+			VictoryRule::Annihilation => {}
+			VictoryRule::Flagship => res.write(self.flagship.unwrap_or_default() as u64, FLAGSHIP_BITS),
+			VictoryRule::Headquarters => {
+				let pos = self.headquarters.unwrap_or(Vec2::new(0, 0));
+				res.write(pos.x.into(), COORD_BITS);
+				res.write(pos.y.into(), COORD_BITS);
+			}
+			VictoryRule::Points { .. } => res.write(self.points.into(), POINTS_BITS)
+		}
+
+		if rules.turns == TurnRule::Simultaneous {
+			res.write_bool(self.pending.is_some());
+			if let Some(pending) = &self.pending {
+				res.write(pending.target.index() as u64, PLAYER_BITS);
+				res.write(pending.tiles.len() as u64, PENDING_BITS);
+				for tile in &pending.tiles {
+					res.write(tile.x.into(), COORD_BITS);
+					res.write(tile.y.into(), COORD_BITS);
+				}
+			}
+		}
+	}
+
+	// Only what the layout of the data depends on is checked here, the rest is up to `validate`
+	fn read(data: &mut BitReader, rules: &GameRules, player: Turn) -> Result<Self, DecodeError> {
+		let user_id = data.read(64)?;
+
+		let (teammate_id, relay) = match rules.mode {
+			GameMode::FreeForAll => (None, false),
+			GameMode::Teams => (Some(data.read(64)?), data.read_bool()?)
+		};
+
+		let mut charges = [0; Weapon::COUNT];
+		if rules.weapons == WeaponRule::Arsenal {
+			for charges in &mut charges {
+				*charges = data.read(CHARGE_BITS)? as u8;
+			}
+		}
+
+		let mut hits = HitMatrix::new();
+		for tile in rules.board_size.tiles() {
+			if data.read_bool()? { hits.set(tile); }
+		}
+
+		let mut ships = Vec::with_capacity(rules.fleet.ships().len());
+		for (index, &info) in rules.fleet.ships().iter().enumerate() {
+			let x = data.read(COORD_BITS)? as u8;
+			let y = data.read(COORD_BITS)? as u8;
+			let rot = Rotation(data.read(ROTATION_BITS)? as u8);
+			let ship = Ship::new(info, ShipState::new(Vec2::new(x, y), rot), rules);
+
+			// Reject ships that would stick out of the board
+			if !rules.grid().is_valid_rotation(rot) || !rules.board_size.contains(ship.state.position()) || !is_on_board(&ship, rules.board_size) {
+				return Err(DecodeError::ShipOutOfBounds { player, index });
+			}
+
+			ships.push(ship);
+		}
+
+		let mut stale = HitMatrix::new();
+		if rules.movement == MovementRule::Moving {
+			for tile in surface_tiles(&ships) {
+				if data.read_bool()? { stale.set(tile); }
+			}
+		}
+
+		let mut damage = DamageMatrix::new();
+		for ship in &ships {
+			let armor = rules.armor.armor(ship.info);
+			if armor == 0 { continue; }
+
+			for tile in ship.tiles().filter(|&t| !hits.get(t) || stale.get(t)) {
+				damage.set(tile, data.read(ARMOR_BITS)? as u8);
+			}
+		}
+
+		let mut depth_hits = HitMatrix::new();
+		if rules.layers == LayerRule::Submerged {
+			for tile in ships.iter().filter(|s| s.layer == Layer::Depth).flat_map(|s| s.tiles()) {
+				if data.read_bool()? { depth_hits.set(tile); }
+			}
+		}
+
+		let mut traps = Vec::with_capacity(rules.traps.layout().len());
+		for &kind in rules.traps.layout() {
+			let pos = Vec2::new(data.read(COORD_BITS)? as u8, data.read(COORD_BITS)? as u8);
+			traps.push(Trap { kind, pos, exposed: data.read_bool()? });
+		}
+
+		let (mut flagship, mut headquarters, mut points) = (None, None, 0);
+		match rules.victory {
+			VictoryRule::Annihilation => {}
+			VictoryRule::Flagship => flagship = Some(data.read(FLAGSHIP_BITS)? as usize),
+			VictoryRule::Headquarters => {
+				headquarters = Some(Vec2::new(data.read(COORD_BITS)? as u8, data.read(COORD_BITS)? as u8));
+			}
+			VictoryRule::Points { .. } => points = data.read(POINTS_BITS)? as u8
+		}
+
+		let mut pending = None;
+		if rules.turns == TurnRule::Simultaneous && data.read_bool()? {
+			let target = Turn(data.read(PLAYER_BITS)? as u8 + 1);
+			let count = data.read(PENDING_BITS)? as usize;
+			let tiles = (0..count)
+				.map(|_| Ok(Vec2::new(data.read(COORD_BITS)? as u8, data.read(COORD_BITS)? as u8)))
+				.collect::<Result<_, DecodeError>>()?;
+
+			pending = Some(PendingShots { target, tiles });
+		}
+
+		Ok(Self {
+			user_id,
+			teammate_id,
+			hits,
+			stale,
+			damage,
+			depth_hits,
+			ships,
+			traps,
+			relay,
+			charges,
+			pending,
+			flagship,
+			headquarters,
+			points
+		})
+	}
+
+	fn validate(&self, player: Turn, rules: &GameRules) -> Result<(), DecodeError> {
+		if self.teammate_id.is_some() != (rules.mode == GameMode::Teams) || (self.relay && self.teammate_id.is_none()) {
+			return Err(DecodeError::Teammate { player });
+		}
+
+		let charges_left = |(weapon, &charges): (Weapon, &u8)| match rules.weapons {
+			WeaponRule::Standard => charges == 0,
+			WeaponRule::Arsenal => charges <= weapon.starting_charges()
+		};
+		if !Weapon::ALL.into_iter().zip(&self.charges).all(charges_left) {
+			return Err(DecodeError::Charges { player });
+		}
+
+		// Every ship has to be where it could have been placed
+		let fleet = rules.fleet.ships();
+		if self.ships.len() != fleet.len() || self.ships.iter().zip(fleet).any(|(s, info)| s.info.index != info.index) {
+			return Err(DecodeError::Rules);
+		}
+
+		for (index, ship) in self.ships.iter().enumerate() {
+			self.check_ship(index, ship, rules).map_err(|error| match error {
+				PlacementError::OutOfBounds | PlacementError::Rotation => DecodeError::ShipOutOfBounds { player, index },
+				PlacementError::Overlap { index: other, .. } => DecodeError::OverlappingShips { player, index, other },
+				error => DecodeError::Placement { player, error }
+			})?;
+		}
+
+		let layout = rules.traps.layout();
+		if self.traps.len() != layout.len() || self.traps.iter().zip(layout).any(|(t, &kind)| t.kind != kind) {
+			return Err(DecodeError::Rules);
+		}
+
+		for (index, trap) in self.traps.iter().enumerate() {
+			self.check_trap(index, trap.pos, rules).map_err(|error| DecodeError::Placement { player, error })?;
+		}
+
+		let objective = match rules.victory {
+			VictoryRule::Annihilation => self.flagship.is_none() && self.headquarters.is_none(),
+			VictoryRule::Flagship => self.flagship.is_some_and(|i| i < self.ships.len()) && self.headquarters.is_none(),
+			VictoryRule::Headquarters => self.flagship.is_none() && self.headquarters.is_some(),
+			VictoryRule::Points { .. } => self.flagship.is_none() && self.headquarters.is_none()
+		};
+		if !objective || (self.points != 0 && !matches!(rules.victory, VictoryRule::Points { .. })) {
+			return Err(DecodeError::Objective { player });
+		}
+
+		if let Some(pos) = self.headquarters {
+			check_headquarters(&self.ships, &self.traps, pos, rules).map_err(|error| DecodeError::Placement { player, error })?;
+		}
+
+		// Hits can only be on the board, stale ones only under ships on the surface, and those below only on submerged ships
+		let on_layer = |layer: Layer| {
+			let mut res = HitMatrix::new();
+			for tile in self.ships.iter().filter(|s| s.layer == layer).flat_map(|s| s.tiles()) {
+				res.set(tile);
+			}
+			res
+		};
+		let (surface, depth) = (on_layer(Layer::Surface), on_layer(Layer::Depth));
+		let board = {
+			let mut res = HitMatrix::new();
+			for tile in rules.board_size.tiles() {
+				res.set(tile);
+			}
+			res
+		};
+
+		for index in 0..2 {
+			let (hits, stale, depth_hits) = (self.hits.0[index], self.stale.0[index], self.depth_hits.0[index]);
+			if hits & !board.0[index] != 0 || stale & !(hits & surface.0[index]) != 0 || depth_hits & !depth.0[index] != 0 {
+				return Err(DecodeError::Hits { player });
+			}
+		}
+
+		// Armor only takes hits while the tile holds
+		for tile in rules.board_size.tiles() {
+			let damage = self.damage.get(tile);
+			if damage == 0 { continue; }
+
+			let armor = self.overlap(tile).map_or(0, |s| rules.armor.armor(s.info));
+			if damage > armor || self.hits.get(tile) {
+				return Err(DecodeError::Damage { player });
+			}
+		}
+
+		if let Some(pending) = &self.pending {
+			if pending.tiles.iter().any(|&t| !rules.board_size.contains(t)) {
+				return Err(DecodeError::PendingShots { player });
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::encode::{DecodeError, Encode, Format};
+	use crate::fleet::Fleet;
+	use crate::phase::Phase;
+	use crate::rules::{ShotRule, TrapRule};
+
+	use super::*;
+
+	// Two players with the classic fleet, as the first version of the bot sent them.
+	// The second player was hit at B4 and it's their turn.
+	fn v1_fixture() -> Vec<u8> {
+		let mut res = Vec::new();
+		for (user_id, hits) in [(111u64, 0u128), (222, 1 << 31)] {
+			res.extend(user_id.to_be_bytes());
+			res.extend(hits.to_be_bytes());
+			// Carrier and battleship across the top, cruiser and submarine standing, destroyer in the middle
+			res.extend([0, 1, 0x80 | (2 * 9 + 2), 0x80 | (3 * 9 + 4), 5 * 9 + 5]);
+		}
+
+		res.push(2);
+		res
+	}
+
+	#[test]
+	fn v1_states_decode() {
+		let data = v1_fixture();
+		assert_eq!(Format::detect(&data), Some(Format::V1));
+
+		let state = GameState::try_decode(&data).unwrap();
+		assert_eq!(state.players()[0].user_id, 111);
+		assert_eq!(state.players()[1].user_id, 222);
+		assert_eq!(state.turn_num(), 2);
+		assert_eq!(state.phase(), Phase::Battle);
+		assert!(state.players()[1].hits.get(Vec2::new(1, 3)));
+
+		let ships: Vec<(Vec2, Rotation)> = state.players()[0].ships().iter()
+			.map(|s| (s.state.position(), s.state.rotation()))
+			.collect();
+		assert_eq!(ships, [
+			(Vec2::new(0, 0), Rotation::HORI),
+			(Vec2::new(0, 1), Rotation::HORI),
+			(Vec2::new(2, 2), Rotation::VERT),
+			(Vec2::new(4, 3), Rotation::VERT),
+			(Vec2::new(5, 5), Rotation::HORI)
+		]);
+
+		// Once read, they are written in the current format
+		let encoded = state.encode();
+		assert_eq!(Format::detect(&encoded), Some(Format::V2));
+		assert_eq!(GameState::try_decode(&encoded).unwrap().encode(), encoded);
+	}
+
+	#[test]
+	fn v1_states_without_hits_take_the_phase_from_the_button() {
+		let mut data = v1_fixture();
+		data[29 + 8..29 + 24].fill(0);
+
+		assert!(matches!(GameState::try_decode_v1(&data, false).unwrap().phase(), Phase::AwaitingPlacement(_)));
+		assert_eq!(GameState::try_decode_v1(&data, true).unwrap().phase(), Phase::Battle);
+	}
+
+	#[test]
+	fn states_round_trip() {
+		let rules = GameRules { board_size: BoardSize::new(12).unwrap(), ..Default::default() };
+		let mut state = GameState::new(&[1, 2, 3], rules).unwrap();
+		assert_eq!(GameState::try_decode(&state.encode()).unwrap().encode(), state.encode());
+
+		while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+		state.fire(&[Vec2::new(3, 4)]).unwrap();
+
+		let decoded = GameState::try_decode(&state.encode()).unwrap();
+		assert_eq!(decoded.encode(), state.encode());
+		assert_eq!(decoded.turn(), state.turn());
+		assert_eq!(decoded.phase(), Phase::Battle);
+	}
+
+	#[test]
+	fn corrupted_states_are_rejected() {
+		let encoded = GameState::new(&[1, 2], GameRules::default()).unwrap().encode();
+
+		for index in 1..encoded.len() {
+			let mut corrupted = encoded.clone();
+			corrupted[index] ^= 0x10;
+			assert_eq!(GameState::try_decode(&corrupted).unwrap_err(), DecodeError::Checksum, "byte {index}");
+		}
+
+		assert!(GameState::try_decode(&encoded[..encoded.len() - 1]).is_err());
+	}
+
+	// Encodes a broken state and reads it back, the way a crafted id would arrive
+	fn decode_broken(state: &GameState) -> Result<GameState, DecodeError> {
+		GameState::try_decode(&state.encode())
+	}
+
+	fn new_game(rules: GameRules) -> GameState {
+		GameState::new(&[1, 2], rules).unwrap()
+	}
+
+	#[test]
+	fn overlapping_ships_are_rejected() {
+		let mut state = new_game(GameRules::default());
+		let board = &mut state.players[1];
+		board.ships[1] = Ship::new(board.ships[1].info, board.ships[0].state, &state.rules);
+
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::OverlappingShips { player: Turn(2), index: 0, other: 1 });
+	}
+
+	#[test]
+	fn ships_off_the_board_are_rejected() {
+		let mut state = new_game(GameRules::default());
+		let board = &mut state.players[0];
+		board.ships[0] = Ship::new(board.ships[0].info, ShipState::new(Vec2::new(9, 9), Rotation::HORI), &state.rules);
+
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::ShipOutOfBounds { player: Turn(1), index: 0 });
+	}
+
+	#[test]
+	fn traps_off_the_board_are_rejected() {
+		let mut state = new_game(GameRules { traps: TrapRule::MinesAndDecoys, ..Default::default() });
+		state.players[0].traps[0].pos = Vec2::new(12, 12);
+
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::Placement { player: Turn(1), error: PlacementError::OutOfBounds });
+	}
+
+	#[test]
+	fn phases_have_to_match_the_boards() {
+		// Nobody won a game that just started
+		let mut state = new_game(GameRules::default());
+		state.phase = Phase::Finished { winner: Some(Turn(1)) };
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::Phase);
+
+		// Placement goes in turn order. The encoding takes the player from the turn, so only states built otherwise can get this wrong
+		let mut state = new_game(GameRules::default());
+		state.phase = Phase::AwaitingPlacement(Turn(2));
+		assert_eq!(state.validate(), Err(DecodeError::Turn));
+
+		// Once a fleet is sunk, the battle is over
+		let mut state = new_game(GameRules::default());
+		while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+		let tiles: Vec<Vec2> = state.players[1].ships.iter().flat_map(|s| s.tiles()).collect();
+		for tile in tiles {
+			state.players[1].hits.set(tile);
+		}
+		assert_eq!(state.validate(), Err(DecodeError::Phase));
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::Phase);
+
+		// And it was won by the player who is left
+		state.phase = Phase::Finished { winner: Some(Turn(2)) };
+		assert_eq!(state.validate(), Err(DecodeError::Phase));
+		state.phase = Phase::Finished { winner: Some(Turn(1)) };
+		assert_eq!(state.validate(), Ok(()));
+	}
+	#[test]
+	fn rounds_have_to_fit_the_points_race() {
+		// Only states built otherwise can have more rounds than the encoding holds
+		let mut state = new_game(GameRules { victory: VictoryRule::Points { rounds: 5 }, ..Default::default() });
+		state.rules.victory = VictoryRule::Points { rounds: VictoryRule::MAX_ROUNDS + 1 };
+		assert_eq!(state.validate(), Err(DecodeError::Rules));
+		state.rules.victory = VictoryRule::Points { rounds: 0 };
+		assert_eq!(state.validate(), Err(DecodeError::Rules));
+
+		// The round can't go past the last one
+		let mut state = new_game(GameRules { victory: VictoryRule::Points { rounds: 5 }, ..Default::default() });
+		state.round = 5;
+		assert_eq!(state.validate(), Ok(()));
+		state.round = 6;
+		assert_eq!(decode_broken(&state).unwrap_err(), DecodeError::Turn);
+
+		// Nor be counted outside of a points race, where the encoding leaves it out
+		let mut state = new_game(GameRules::default());
+		state.round = 1;
+		assert_eq!(state.validate(), Err(DecodeError::Turn));
+	}
+	#[test]
+	fn full_salvos_round_trip() {
+		let fleet = Fleet::new(vec![&ShipInfo::DESTROYER; Fleet::MAX_SHIPS]).unwrap();
+		let rules = GameRules { fleet, shots: ShotRule::Salvo, turns: TurnRule::Simultaneous, ..Default::default() };
+		let mut state = new_game(rules);
+		while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+
+		// Every ship is still afloat, so every one of them shoots
+		let tiles: Vec<Vec2> = state.rules.board_size.tiles().take(Fleet::MAX_SHIPS).collect();
+		assert_eq!(state.lock_in(&tiles), Ok(None));
+
+		let decoded = GameState::try_decode(&state.encode()).unwrap();
+		assert_eq!(decoded.players[0].pending().map(|p| p.tiles.len()), Some(Fleet::MAX_SHIPS));
+		assert_eq!(decoded.encode(), state.encode());
 	}
 }
@@ -0,0 +1,160 @@
+use crate::game_state::{BoardSize, Rotation, ShipInfo, Vec2};
+use crate::rules::{GameRules, TopologyRule};
+
+/// The tiles of a board and how they connect, as chosen by the [`TopologyRule`].
+///
+/// Hex boards use offset coordinates, where every odd row is shifted half a tile to the right.
+/// That way tiles are named and stored just like on square boards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+	topology: TopologyRule,
+	board_size: BoardSize
+}
+
+// Neighbours on hex boards, which depend on whether the row is shifted
+const HEX_EVEN_NEIGHBOURS: [(i16, i16); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1)];
+const HEX_ODD_NEIGHBOURS: [(i16, i16); 6] = [(1, 0), (1, 1), (0, 1), (-1, 0), (0, -1), (1, -1)];
+
+// The straight ships on hex boards lie along one of three axes
+const HEX_AXES: u8 = 3;
+
+impl GameRules {
+	pub fn grid(&self) -> Grid {
+		Grid { topology: self.topology, board_size: self.board_size }
+	}
+}
+
+impl Grid {
+	pub fn board_size(self) -> BoardSize {
+		self.board_size
+	}
+
+	pub fn topology(self) -> TopologyRule {
+		self.topology
+	}
+
+	/// The tile `dx` columns and `dy` rows away from `pos`, or None if that is off the board.
+	pub fn offset(self, pos: Vec2, dx: i16, dy: i16) -> Option<Vec2> {
+		let size = i16::from(self.board_size.get());
+		let (x, y) = (i16::from(pos.x) + dx, i16::from(pos.y) + dy);
+
+		match self.topology {
+			TopologyRule::Flat | TopologyRule::Hex => {
+				let inside = (0..size).contains(&x) && (0..size).contains(&y);
+				inside.then(|| Vec2::new(x as u8, y as u8))
+			}
+			TopologyRule::Wrapping => Some(Vec2::new(x.rem_euclid(size) as u8, y.rem_euclid(size) as u8))
+		}
+	}
+
+	/// The tiles sharing an edge with `pos`. That's four on square boards and six on hex boards.
+	pub fn neighbours(self, pos: Vec2) -> Vec<Vec2> {
+		let offsets: &[(i16, i16)] = match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+			TopologyRule::Hex if pos.y.is_multiple_of(2) => &HEX_EVEN_NEIGHBOURS,
+			TopologyRule::Hex => &HEX_ODD_NEIGHBOURS
+		};
+
+		offsets.iter()
+			.filter_map(|&(dx, dy)| self.offset(pos, dx, dy))
+			.filter(|&t| t != pos)
+			.collect()
+	}
+
+	/// The tile and every tile touching it, including diagonally on square boards.
+	pub fn surroundings(self, pos: Vec2) -> Vec<Vec2> {
+		match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => (-1..=1)
+				.flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+				.filter_map(|(dx, dy)| self.offset(pos, dx, dy))
+				.collect(),
+			TopologyRule::Hex => std::iter::once(pos).chain(self.neighbours(pos)).collect()
+		}
+	}
+
+	/// The amount of steps between two tiles. Diagonal steps count on square boards,
+	/// and so does crossing the edge on wrapping boards.
+	pub fn distance(self, a: Vec2, b: Vec2) -> u8 {
+		let size = self.board_size.get();
+		let (dx, dy) = (a.x.abs_diff(b.x), a.y.abs_diff(b.y));
+
+		match self.topology {
+			TopologyRule::Flat => dx.max(dy),
+			TopologyRule::Wrapping => dx.min(size - dx).max(dy.min(size - dy)),
+			TopologyRule::Hex => {
+				// Axial coordinates make hex distances easy
+				let (aq, ar) = to_axial(a);
+				let (bq, br) = to_axial(b);
+				let (dq, dr) = (aq - bq, ar - br);
+				((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u8
+			}
+		}
+	}
+
+	pub fn are_neighbours(self, a: Vec2, b: Vec2) -> bool {
+		self.neighbours(a).contains(&b)
+	}
+
+	/// The rotations that result in different layouts of the ship.
+	/// On hex boards, only straight ships fit, so there are none for other shapes.
+	pub fn orientations(self, info: &'static ShipInfo) -> Vec<Rotation> {
+		match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => info.orientations(),
+			TopologyRule::Hex if info.is_straight() => (0..HEX_AXES).map(|axis| Rotation::new(axis, false)).collect(),
+			TopologyRule::Hex => Vec::new()
+		}
+	}
+
+	/// Whether a ship may face this way. Only the first turns are used on hex boards, one for every axis.
+	pub fn is_valid_rotation(self, rot: Rotation) -> bool {
+		match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => true,
+			TopologyRule::Hex => !rot.is_mirrored() && rot.quarter_turns() < HEX_AXES
+		}
+	}
+
+	/// The next orientation clockwise.
+	pub fn turn(self, rot: Rotation) -> Rotation {
+		match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => Rotation::new(rot.quarter_turns() + 1, rot.is_mirrored()),
+			TopologyRule::Hex => Rotation::new((rot.quarter_turns() + 1) % HEX_AXES, false)
+		}
+	}
+
+	/// The tiles of a ship at `pos`, facing `rot`.
+	/// Tiles off the board are left out of bounds, rather than ending up on the other side.
+	pub(crate) fn ship_tiles(self, pos: Vec2, rot: Rotation, shape: &'static [Vec2]) -> impl Iterator<Item = Vec2> {
+		let size = self.board_size.get();
+
+		// Straight ships on hex boards are laid out along their axis instead
+		let layout = match self.topology {
+			TopologyRule::Flat | TopologyRule::Wrapping => rot,
+			TopologyRule::Hex => Rotation::HORI
+		};
+
+		layout.apply(shape).map(move |t| match self.topology {
+			TopologyRule::Flat => Vec2::new(pos.x + t.x, pos.y + t.y),
+			TopologyRule::Wrapping => Vec2::new((pos.x + t.x) % size, (pos.y + t.y) % size),
+			TopologyRule::Hex => hex_step(pos, rot.quarter_turns(), t.x)
+		})
+	}
+}
+
+// Odd rows are shifted right, so going down alternates between keeping and changing the column
+fn hex_step(pos: Vec2, axis: u8, steps: u8) -> Vec2 {
+	let (x, y) = (i16::from(pos.x), i16::from(pos.y));
+	let (steps, odd) = (i16::from(steps), y % 2);
+
+	let (x, y) = match axis {
+		0 => (x + steps, y),
+		1 => (x + (steps + odd) / 2, y + steps),
+		_ => (x - (steps + 1 - odd) / 2, y + steps)
+	};
+
+	Vec2::new(u8::try_from(x).unwrap_or(u8::MAX), u8::try_from(y).unwrap_or(u8::MAX))
+}
+
+fn to_axial(pos: Vec2) -> (i16, i16) {
+	let (x, y) = (i16::from(pos.x), i16::from(pos.y));
+	(x - (y - (y & 1)) / 2, y)
+}
@@ -1,2 +1,17 @@
 pub mod game_state;
+pub mod fleet;
+pub mod rules;
+pub mod shot;
+pub mod weapon;
+pub mod trap;
+pub mod movement;
+pub mod round;
+pub mod terrain;
+pub mod grid;
+pub mod depth;
+pub mod victory;
+pub mod event;
+pub mod phase;
 pub mod encode;
+#[cfg(feature = "serde")]
+mod serialize;
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::event::GameEvent;
+use crate::game_state::{GameState, PlacementError, Rotation, ShipInfo, ShipState, Turn, Vec2};
+use crate::phase::PhaseError;
+use crate::rules::{GameRules, MovementRule, TurnRule};
+
+/// What a player may do with one of their ships instead of firing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShipMove {
+	Up,
+	Down,
+	Left,
+	Right,
+	/// A clockwise turn around the first tile, to the next axis on hex boards.
+	Turn
+}
+
+/// A ship that was moved. The opponents should only be told that some ship moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveReport {
+	pub mover: Turn,
+	pub mover_id: u64,
+	pub info: &'static ShipInfo,
+	pub ship_move: ShipMove,
+	/// Whether that was the last turn of a points race.
+	pub game_over: bool
+}
+
+/// Why a ship couldn't be moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+	/// The rules don't allow moving ships.
+	NotAllowed,
+	/// Only ships that weren't hit yet may move.
+	Damaged(&'static ShipInfo),
+	Placement(PlacementError),
+	/// The battle isn't on.
+	Phase(PhaseError)
+}
+
+impl ShipMove {
+	pub const ALL: [ShipMove; 5] = [ShipMove::Up, ShipMove::Down, ShipMove::Left, ShipMove::Right, ShipMove::Turn];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			ShipMove::Up => "up",
+			ShipMove::Down => "down",
+			ShipMove::Left => "left",
+			ShipMove::Right => "right",
+			ShipMove::Turn => "turn"
+		}
+	}
+
+	// The new position and rotation, or None if the ship would leave the board
+	fn apply(self, pos: Vec2, rot: Rotation, rules: &GameRules) -> Option<(Vec2, Rotation)> {
+		let step = |dx, dy| rules.grid().offset(pos, dx, dy);
+		match self {
+			ShipMove::Up => Some((step(0, -1)?, rot)),
+			ShipMove::Down => Some((step(0, 1)?, rot)),
+			ShipMove::Left => Some((step(-1, 0)?, rot)),
+			ShipMove::Right => Some((step(1, 0)?, rot)),
+			ShipMove::Turn => Some((pos, rules.grid().turn(rot)))
+		}
+	}
+}
+
+impl GameState {
+	/// Moves one of the current player's ships instead of firing, then passes the turn.
+	/// The ship has to be intact and follows the same placement rules as before the battle.
+	pub fn move_ship(&mut self, index: usize, ship_move: ShipMove) -> Result<MoveReport, MoveError> {
+		self.phase().check_battle().map_err(MoveError::Phase)?;
+
+		if self.rules().movement != MovementRule::Moving || self.rules().turns == TurnRule::Simultaneous {
+			return Err(MoveError::NotAllowed);
+		}
+
+		let rules = self.rules().clone();
+		let player = self.current_mut();
+		let ship = *player.ships().get(index).ok_or(MoveError::Placement(PlacementError::UnknownShip))?;
+		if !player.is_intact(&ship) { return Err(MoveError::Damaged(ship.info)); }
+
+		let (pos, rot) = ship_move
+			.apply(ship.state.position(), ship.state.rotation(), &rules)
+			.ok_or(MoveError::Placement(PlacementError::OutOfBounds))?;
+		player.place_ship(index, pos, rot, &rules).map_err(MoveError::Placement)?;
+		self.log(GameEvent::ShipPlaced { player: self.turn(), index, state: ShipState::new(pos, rot) });
+
+		let mut report = MoveReport {
+			mover: self.turn(),
+			mover_id: self.current().shooter_id(),
+			info: ship.info,
+			ship_move,
+			game_over: false
+		};
+
+		self.pass_turn();
+		report.game_over = self.is_over();
+		self.finish();
+		Ok(report)
+	}
+}
+
+impl Display for MoveError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MoveError::NotAllowed => write!(f, "Ships can't move in this game."),
+			MoveError::Damaged(info) => write!(f, "The {} was already hit and can't move anymore.", info.label),
+			MoveError::Placement(err) => err.fmt(f),
+			MoveError::Phase(err) => err.fmt(f)
+		}
+	}
+}
+
+impl Error for MoveError {}
+
+#[cfg(test)]
+mod tests {
+	use crate::encode::Encode;
+	use crate::game_state::BoardSize;
+	use crate::phase::Phase;
+
+	use super::*;
+
+	#[test]
+	fn moving_ships_keep_the_encoded_length() {
+		let rules = GameRules { movement: MovementRule::Moving, board_size: BoardSize::new(11).unwrap(), ..Default::default() };
+		let mut stale = 0;
+
+		// Ships are placed at random, so try until a ship can move onto a miss next to it
+		for _ in 0..100 {
+			let mut state = GameState::new(&[1, 2], rules.clone()).unwrap();
+			while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+			let len = state.encode().len();
+
+			let ship = state.target().ships()[0];
+			let Some(tile) = ship.tiles()
+				.map(|t| Vec2::new(t.x + 1, t.y))
+				.find(|&t| rules.board_size.contains(t) && state.target().overlap(t).is_none()) else { continue; };
+
+			state.fire(&[tile]).unwrap();
+			assert_eq!(state.encode().len(), len);
+			if state.move_ship(0, ShipMove::Right).is_err() { continue; }
+			assert_eq!(state.encode().len(), len);
+
+			let target = state.player(Turn::new(2, 2).unwrap());
+			if target.is_stale(tile) { stale += 1; }
+
+			state.fire(&[tile]).unwrap();
+			assert_eq!(state.encode().len(), len);
+		}
+
+		assert!(stale > 0);
+	}
+}
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::event::GameEvent;
+use crate::game_state::{GameState, Turn};
+
+/// Where the game is at. Every action belongs to one phase and is rejected in the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Phase {
+	/// The player is arranging their board. Everyone does so in turn order before the battle.
+	AwaitingPlacement(Turn),
+	/// Everyone is firing at each other.
+	Battle,
+	/// The game is over, won by the player unless it was a draw, see [`GameState::winner`].
+	Finished { winner: Option<Turn> }
+}
+
+/// Why an action isn't allowed at this point of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseError {
+	/// Boards can only be arranged before the battle.
+	BattleStarted,
+	/// Shots can only be fired once every player arranged their board.
+	NotStarted,
+	/// Nothing happens anymore once the game is over.
+	Over
+}
+
+impl Phase {
+	/// Checks that boards are still being arranged.
+	pub fn check_placement(self) -> Result<(), PhaseError> {
+		match self {
+			Phase::AwaitingPlacement(_) => Ok(()),
+			Phase::Battle => Err(PhaseError::BattleStarted),
+			Phase::Finished { .. } => Err(PhaseError::Over)
+		}
+	}
+
+	/// Checks that the battle is on.
+	pub fn check_battle(self) -> Result<(), PhaseError> {
+		match self {
+			Phase::AwaitingPlacement(_) => Err(PhaseError::NotStarted),
+			Phase::Battle => Ok(()),
+			Phase::Finished { .. } => Err(PhaseError::Over)
+		}
+	}
+}
+
+impl GameState {
+	/// Ends the placement of the current player. The next player arranges their board,
+	/// or once everyone did, the battle starts with the first player.
+	pub fn confirm_placement(&mut self) -> Result<Phase, PhaseError> {
+		self.phase().check_placement()?;
+
+		let player = self.turn();
+		self.record(GameEvent::PlacementConfirmed { player });
+
+		let count = self.players().len();
+		let next = Turn::new(((player.index() + 1) % count) as u8 + 1, count).unwrap_or(player);
+		let target = self.opponents_of(next).next().unwrap_or(next);
+		self.set_turn(next, target);
+
+		Ok(self.phase())
+	}
+
+	// Where a confirmed placement leads
+	pub(crate) fn phase_after_placement(&self, player: Turn) -> Phase {
+		let count = self.players().len();
+		match Turn::new(player.num() + 1, count) {
+			Some(next) => Phase::AwaitingPlacement(next),
+			None => Phase::Battle
+		}
+	}
+}
+
+impl Display for PhaseError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PhaseError::BattleStarted => write!(f, "The battle has already started."),
+			PhaseError::NotStarted => write!(f, "The battle hasn't started yet."),
+			PhaseError::Over => write!(f, "The game is already over.")
+		}
+	}
+}
+
+impl Error for PhaseError {}
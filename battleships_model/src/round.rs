@@ -0,0 +1,94 @@
+use crate::depth::Layer;
+use crate::event::GameEvent;
+use crate::game_state::{GameState, Turn, Vec2};
+use crate::shot::{FireError, FireReport};
+
+/// Shots a player locked in, which land once everyone else locked in theirs as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingShots {
+	pub target: Turn,
+	pub tiles: Vec<Vec2>
+}
+
+/// Everything that happened once a round of locked in shots landed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundReport {
+	/// The shots of every player, in turn order.
+	pub reports: Vec<FireReport>,
+	/// The players who dropped out of the game this round.
+	pub defeated: Vec<Turn>,
+	/// Whether the game is over. If there is no winner, the round ended in a draw.
+	pub game_over: bool
+}
+
+impl GameState {
+	/// Locks in the current player's shots and passes the turn without firing them.
+	/// Once every player still in the game locked in, all shots land at once.
+	pub fn lock_in(&mut self, tiles: &[Vec2]) -> Result<Option<RoundReport>, FireError> {
+		self.check_shots(tiles)?;
+
+		let shots = PendingShots { target: self.target_turn(), tiles: tiles.to_vec() };
+		self.record(GameEvent::ShotsLockedIn { player: self.turn(), shots });
+		self.next_turn();
+
+		// Once the turn comes back around, everyone has locked in
+		if self.current().pending().is_none() {
+			return Ok(None);
+		}
+
+		Ok(Some(self.resolve_round()))
+	}
+
+	// Fires all locked in shots, starting with the current player
+	fn resolve_round(&mut self) -> RoundReport {
+		let count = self.players().len();
+		let first = self.turn().index();
+		let order: Vec<Turn> = (0..count)
+			.filter_map(|i| Turn::new(((first + i) % count) as u8 + 1, count))
+			.collect();
+
+		let alive_before: Vec<bool> = self.players().iter().map(|p| !p.is_defeated()).collect();
+		let movement = self.rules().movement;
+
+		let mut reports = Vec::new();
+		for &turn in &order {
+			let Some(pending) = self.player(turn).pending().cloned() else { continue; };
+
+			self.set_turn(turn, pending.target);
+
+			// Someone else may have hit the same tiles earlier in the round
+			let target = self.target();
+			let tiles: Vec<Vec2> = pending.tiles.into_iter().filter(|&t| target.is_open(t, Layer::Surface, movement)).collect();
+			if !tiles.is_empty() {
+				let mut report = self.apply_shots(&tiles, None, Layer::Surface);
+				report.shoot_again = false;
+				reports.push(report);
+			}
+
+			// Teammates take turns locking in, so the relay only passes once their shots landed
+			self.record(GameEvent::TurnEnded { player: turn });
+		}
+
+		let game_over = self.is_over();
+		for report in &mut reports {
+			report.game_over = game_over;
+		}
+
+		let defeated = order.iter()
+			.copied()
+			.filter(|t| alive_before[t.index()] && self.player(*t).is_defeated())
+			.collect();
+
+		// The next round starts with the first player still standing
+		let next = order.iter().find(|t| !self.player(**t).is_defeated());
+		if let (false, Some(&next)) = (game_over, next) {
+			let target = self.opponents_of(next).next().unwrap_or(next);
+			self.set_turn(next, target);
+		}
+
+		self.finish();
+
+		RoundReport { reports, defeated, game_over }
+	}
+}
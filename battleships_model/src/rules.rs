@@ -0,0 +1,354 @@
+use crate::encode::{BitReader, BitWriter, DecodeError};
+use crate::depth::Layer;
+use crate::fleet::Fleet;
+use crate::game_state::{BoardSize, ShipInfo};
+use crate::terrain::{Terrain, TerrainPreset};
+use crate::trap::TrapKind;
+
+/// The variations of the game both players agreed on.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRules {
+	pub board_size: BoardSize,
+	pub fleet: Fleet,
+	pub placement: PlacementRule,
+	pub shots: ShotRule,
+	pub turns: TurnRule,
+	pub mode: GameMode,
+	pub weapons: WeaponRule,
+	pub traps: TrapRule,
+	pub movement: MovementRule,
+	pub armor: ArmorRule,
+	pub terrain: TerrainRule,
+	pub topology: TopologyRule,
+	pub layers: LayerRule,
+	pub victory: VictoryRule
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlacementRule {
+	/// Ships may not overlap, but may be right next to each other.
+	#[default]
+	Touching,
+	/// Ships need at least one tile of water around them, including diagonally.
+	/// Since nothing can be next to a sunk ship, its surroundings may be marked as misses right away.
+	NoTouching { mark_surroundings: bool }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShotRule {
+	/// One shot per turn.
+	#[default]
+	Single,
+	/// One shot per turn for every ship the player has left.
+	Salvo
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TurnRule {
+	/// The turn passes after every shot.
+	#[default]
+	Alternating,
+	/// The turn only passes once the player misses.
+	ShootAgainOnHit,
+	/// Every player locks in their shots without seeing the others' and they all land at once.
+	Simultaneous
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameMode {
+	/// Every player has their own board and fights everyone else.
+	#[default]
+	FreeForAll,
+	/// Two teams of two share a board each. Teammates take turns firing for their team.
+	Teams
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WeaponRule {
+	/// Only regular shots.
+	#[default]
+	Standard,
+	/// Every player gets a few charges of special weapons.
+	Arsenal
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrapRule {
+	/// Only ships are on the board.
+	#[default]
+	NoTraps,
+	/// Every player also places mines and decoys.
+	MinesAndDecoys
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MovementRule {
+	/// Ships stay where they were placed.
+	#[default]
+	Fixed,
+	/// Instead of firing, a player may move an undamaged ship by one tile or turn it.
+	Moving
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArmorRule {
+	/// Every tile is destroyed by a single hit.
+	#[default]
+	Unarmored,
+	/// The tiles of heavy ship classes take more than one hit, see [`ShipInfo::armor`].
+	Armored
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TerrainRule {
+	/// Nothing but water.
+	#[default]
+	OpenSea,
+	/// Islands and reefs scattered from a seed, see [`TerrainRule::layout`].
+	Seeded(u16),
+	/// One of the hand-made maps.
+	Preset(TerrainPreset)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TopologyRule {
+	/// The board ends at its edges.
+	#[default]
+	Flat,
+	/// Leaving the board on one side enters it again on the other, so ships may cross the edges.
+	Wrapping,
+	/// Every tile has six neighbours and straight ships lie along one of three axes, see [`Grid`](crate::grid::Grid).
+	Hex
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LayerRule {
+	/// Every ship sails on the surface.
+	#[default]
+	Surface,
+	/// Submarines dive, where only depth charges reach them, see [`LayerRule::layer`].
+	Submerged
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VictoryRule {
+	/// Players are out once all their ships are sunk.
+	#[default]
+	Annihilation,
+	/// Every fleet has a secret flagship. Losing it means losing the game.
+	Flagship,
+	/// Every player hides their headquarters on an empty tile. Losing it means losing the game.
+	Headquarters,
+	/// Once the rounds are up, the player with the most points wins.
+	/// Sinking a ship is worth a point for every tile of it.
+	Points { rounds: u8 }
+}
+
+impl GameRules {
+	pub(crate) fn write(&self, res: &mut BitWriter) {
+		res.write(self.board_size.get().into(), 8);
+		self.fleet.write(res);
+		self.placement.write(res);
+		res.write_bool(self.shots == ShotRule::Salvo);
+		self.turns.write(res);
+		res.write_bool(self.mode == GameMode::Teams);
+		res.write_bool(self.weapons == WeaponRule::Arsenal);
+		res.write_bool(self.traps == TrapRule::MinesAndDecoys);
+		res.write_bool(self.movement == MovementRule::Moving);
+		res.write_bool(self.armor == ArmorRule::Armored);
+		self.terrain.write(res);
+		self.topology.write(res);
+		res.write_bool(self.layers == LayerRule::Submerged);
+		self.victory.write(res);
+	}
+
+	pub(crate) fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		Ok(Self {
+			board_size: BoardSize::new(data.read(8)? as u8).ok_or(DecodeError::Rules)?,
+			fleet: Fleet::read(data)?,
+			placement: PlacementRule::read(data)?,
+			shots: if data.read_bool()? { ShotRule::Salvo } else { ShotRule::Single },
+			turns: TurnRule::read(data)?,
+			mode: if data.read_bool()? { GameMode::Teams } else { GameMode::FreeForAll },
+			weapons: if data.read_bool()? { WeaponRule::Arsenal } else { WeaponRule::Standard },
+			traps: if data.read_bool()? { TrapRule::MinesAndDecoys } else { TrapRule::NoTraps },
+			movement: if data.read_bool()? { MovementRule::Moving } else { MovementRule::Fixed },
+			armor: if data.read_bool()? { ArmorRule::Armored } else { ArmorRule::Unarmored },
+			terrain: TerrainRule::read(data)?,
+			topology: TopologyRule::read(data)?,
+			layers: if data.read_bool()? { LayerRule::Submerged } else { LayerRule::Surface },
+			victory: VictoryRule::read(data)?
+		})
+	}
+}
+
+impl TurnRule {
+	fn write(self, res: &mut BitWriter) {
+		res.write(self as u64, 2);
+	}
+
+	fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		match data.read(2)? {
+			0 => Ok(TurnRule::Alternating),
+			1 => Ok(TurnRule::ShootAgainOnHit),
+			2 => Ok(TurnRule::Simultaneous),
+			_ => Err(DecodeError::Rules)
+		}
+	}
+}
+
+impl TerrainRule {
+	/// Where the terrain is. Every board of the game shares the same layout.
+	pub fn layout(self, board_size: BoardSize) -> Terrain {
+		match self {
+			TerrainRule::OpenSea => Terrain::default(),
+			TerrainRule::Seeded(seed) => Terrain::seeded(seed, board_size),
+			TerrainRule::Preset(preset) => preset.layout(board_size)
+		}
+	}
+
+	fn write(self, res: &mut BitWriter) {
+		match self {
+			TerrainRule::OpenSea => res.write(0, 2),
+			TerrainRule::Seeded(seed) => {
+				res.write(1, 2);
+				res.write(seed.into(), 16);
+			}
+			TerrainRule::Preset(preset) => {
+				res.write(2, 2);
+				res.write(preset.index() as u64, 2);
+			}
+		}
+	}
+
+	fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		match data.read(2)? {
+			0 => Ok(TerrainRule::OpenSea),
+			1 => Ok(TerrainRule::Seeded(data.read(16)? as u16)),
+			2 => TerrainPreset::ALL.get(data.read(2)? as usize).map(|&p| TerrainRule::Preset(p)).ok_or(DecodeError::Rules),
+			_ => Err(DecodeError::Rules)
+		}
+	}
+}
+
+impl TopologyRule {
+	fn write(self, res: &mut BitWriter) {
+		res.write(self as u64, 2);
+	}
+
+	fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		match data.read(2)? {
+			0 => Ok(TopologyRule::Flat),
+			1 => Ok(TopologyRule::Wrapping),
+			2 => Ok(TopologyRule::Hex),
+			_ => Err(DecodeError::Rules)
+		}
+	}
+}
+
+impl LayerRule {
+	/// Where ships of this class sail.
+	pub fn layer(self, info: &ShipInfo) -> Layer {
+		match self {
+			LayerRule::Submerged if info.index == ShipInfo::SUBMARINE.index => Layer::Depth,
+			_ => Layer::Surface
+		}
+	}
+}
+
+impl VictoryRule {
+	pub const MAX_ROUNDS: u8 = (1 << ROUND_BITS) - 1;
+	pub const DEFAULT_ROUNDS: u8 = 30;
+
+	fn write(self, res: &mut BitWriter) {
+		match self {
+			VictoryRule::Annihilation => res.write(0, 2),
+			VictoryRule::Flagship => res.write(1, 2),
+			VictoryRule::Headquarters => res.write(2, 2),
+			VictoryRule::Points { rounds } => {
+				res.write(3, 2);
+				res.write(rounds.into(), ROUND_BITS);
+			}
+		}
+	}
+
+	fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		match data.read(2)? {
+			0 => Ok(VictoryRule::Annihilation),
+			1 => Ok(VictoryRule::Flagship),
+			2 => Ok(VictoryRule::Headquarters),
+			_ => match data.read(ROUND_BITS)? as u8 {
+				0 => Err(DecodeError::Rules),
+				rounds => Ok(VictoryRule::Points { rounds })
+			}
+		}
+	}
+}
+
+/// Enough for [`VictoryRule::MAX_ROUNDS`].
+pub(crate) const ROUND_BITS: u8 = 6;
+
+impl ArmorRule {
+	/// The extra hits every tile of the ship takes before it is destroyed.
+	pub fn armor(self, info: &ShipInfo) -> u8 {
+		match self {
+			ArmorRule::Unarmored => 0,
+			ArmorRule::Armored => info.armor
+		}
+	}
+}
+
+impl TrapRule {
+	/// The traps every player places.
+	pub fn layout(self) -> &'static [TrapKind] {
+		match self {
+			TrapRule::NoTraps => &[],
+			TrapRule::MinesAndDecoys => &TrapKind::LAYOUT
+		}
+	}
+}
+
+impl PlacementRule {
+	/// The amount of tiles that have to be between ships.
+	pub fn spacing(self) -> u8 {
+		match self {
+			PlacementRule::Touching => 0,
+			PlacementRule::NoTouching { .. } => 1
+		}
+	}
+
+	pub fn marks_surroundings(self) -> bool {
+		matches!(self, PlacementRule::NoTouching { mark_surroundings: true })
+	}
+
+	fn write(self, res: &mut BitWriter) {
+		match self {
+			PlacementRule::Touching => res.write_bool(false),
+			PlacementRule::NoTouching { mark_surroundings } => {
+				res.write_bool(true);
+				res.write_bool(mark_surroundings);
+			}
+		}
+	}
+
+	fn read(data: &mut BitReader) -> Result<Self, DecodeError> {
+		if data.read_bool()? {
+			Ok(PlacementRule::NoTouching { mark_surroundings: data.read_bool()? })
+		} else {
+			Ok(PlacementRule::Touching)
+		}
+	}
+}
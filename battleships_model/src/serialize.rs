@@ -0,0 +1,351 @@
+//! Serde support for the model, enabled by the `serde` feature.
+//!
+//! Human-readable formats get the same names players see: tiles are `"B3"`, rotations are `"H"`, `"V"`
+//! or quarter turns like `"2M"`, and ships are `{ "kind": "Carrier", "pos": "B3", "rot": "H" }`.
+//! Compact formats get plain numbers instead. Game states are validated when they are read back,
+//! just like decoded ones, see [`GameState::validate`].
+
+use std::fmt::{Formatter, Write};
+use std::marker::PhantomData;
+
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::fleet::Fleet;
+use crate::game_state::{BoardSize, DamageMatrix, GameState, HitMatrix, PlayerState, Rotation, Ship, ShipInfo, ShipState, Turn, Vec2};
+use crate::phase::Phase;
+use crate::rules::GameRules;
+
+/// The tiles any board can have, which bounds every tile that is read.
+fn all_tiles() -> impl Iterator<Item = Vec2> {
+	(0..BoardSize::MAX).flat_map(|y| (0..BoardSize::MAX).map(move |x| Vec2::new(x, y)))
+}
+
+// The entries of a map, for keys that can't be hashed or ordered
+fn map_entries<'de, D, K, V>(deserializer: D) -> Result<Vec<(K, V)>, D::Error>
+where D: Deserializer<'de>, K: Deserialize<'de>, V: Deserialize<'de> {
+	struct EntryVisitor<K, V>(PhantomData<(K, V)>);
+
+	impl<'de, K: Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for EntryVisitor<K, V> {
+		type Value = Vec<(K, V)>;
+
+		fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+			f.write_str("a map")
+		}
+
+		fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+			let mut res = Vec::new();
+			while let Some(entry) = map.next_entry()? {
+				res.push(entry);
+			}
+
+			Ok(res)
+		}
+	}
+
+	deserializer.deserialize_map(EntryVisitor(PhantomData))
+}
+
+impl Serialize for Vec2 {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		if serializer.is_human_readable() {
+			let column = char::from(b'A' + self.x);
+			serializer.collect_str(&format_args!("{column}{}", self.y + 1))
+		} else {
+			(self.x, self.y).serialize(serializer)
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for Vec2 {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let pos = if deserializer.is_human_readable() {
+			let text = String::deserialize(deserializer)?;
+			parse_tile(&text).ok_or_else(|| de::Error::custom(format!("invalid tile `{text}`")))?
+		} else {
+			let (x, y) = <(u8, u8)>::deserialize(deserializer)?;
+			Vec2::new(x, y)
+		};
+
+		if pos.x >= BoardSize::MAX || pos.y >= BoardSize::MAX {
+			return Err(de::Error::custom("tile out of bounds"));
+		}
+
+		Ok(pos)
+	}
+}
+
+// A column letter followed by the row number, like `B3`
+fn parse_tile(text: &str) -> Option<Vec2> {
+	let mut chars = text.chars();
+	let column = chars.next()?.to_ascii_uppercase();
+	if !column.is_ascii_uppercase() { return None; }
+
+	let row: u8 = chars.as_str().parse().ok()?;
+	Some(Vec2::new(column as u8 - b'A', row.checked_sub(1)?))
+}
+
+impl Serialize for Rotation {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		if !serializer.is_human_readable() {
+			return (self.quarter_turns() + if self.is_mirrored() { 4 } else { 0 }).serialize(serializer);
+		}
+
+		let mut text = String::new();
+		match *self {
+			Rotation::HORI => text.push('H'),
+			Rotation::VERT => text.push('V'),
+			_ => {
+				let _ = write!(text, "{}", self.quarter_turns());
+				if self.is_mirrored() { text.push('M'); }
+			}
+		}
+
+		serializer.serialize_str(&text)
+	}
+}
+
+impl<'de> Deserialize<'de> for Rotation {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		if !deserializer.is_human_readable() {
+			let value = u8::deserialize(deserializer)?;
+			if value >= 8 { return Err(de::Error::custom("invalid rotation")); }
+			return Ok(Rotation::new(value % 4, value >= 4));
+		}
+
+		let text = String::deserialize(deserializer)?;
+		let rot = match text.as_str() {
+			"H" => Some(Rotation::HORI),
+			"V" => Some(Rotation::VERT),
+			_ => {
+				let (turns, mirrored) = match text.strip_suffix('M') {
+					Some(turns) => (turns, true),
+					None => (text.as_str(), false)
+				};
+				turns.parse().ok().filter(|&t: &u8| t < 4).map(|t| Rotation::new(t, mirrored))
+			}
+		};
+
+		rot.ok_or_else(|| de::Error::custom(format!("invalid rotation `{text}`")))
+	}
+}
+
+/// Players are numbered from 1, like everywhere else they are shown.
+impl Serialize for Turn {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.num().serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Turn {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let num = u8::deserialize(deserializer)?;
+		Turn::new(num, GameState::MAX_PLAYERS).ok_or_else(|| de::Error::custom(format!("invalid player {num}")))
+	}
+}
+
+impl Serialize for BoardSize {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.get().serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for BoardSize {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let size = u8::deserialize(deserializer)?;
+		BoardSize::new(size).ok_or_else(|| de::Error::custom(format!("invalid board size {size}")))
+	}
+}
+
+/// Ship classes go by their label.
+impl Serialize for ShipInfo {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.label)
+	}
+}
+
+impl<'de> Deserialize<'de> for &'static ShipInfo {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct LabelVisitor;
+
+		impl Visitor<'_> for LabelVisitor {
+			type Value = &'static ShipInfo;
+
+			fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+				f.write_str("the label of a ship")
+			}
+
+			fn visit_str<E: de::Error>(self, label: &str) -> Result<Self::Value, E> {
+				ShipInfo::ALL.into_iter()
+					.find(|info| info.label == label)
+					.ok_or_else(|| E::custom(format!("unknown ship `{label}`")))
+			}
+		}
+
+		deserializer.deserialize_str(LabelVisitor)
+	}
+}
+
+impl Serialize for Fleet {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.ships().serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Fleet {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let ships = Vec::<&'static ShipInfo>::deserialize(deserializer)?;
+		Fleet::new(ships).ok_or_else(|| de::Error::custom("invalid fleet"))
+	}
+}
+
+/// Only the tiles that were hit are listed.
+impl Serialize for HitMatrix {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		// Collected first, since compact formats need to know the length up front
+		let tiles: Vec<Vec2> = all_tiles().filter(|&t| self.get(t)).collect();
+		tiles.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for HitMatrix {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let mut res = HitMatrix::new();
+		for tile in Vec::<Vec2>::deserialize(deserializer)? {
+			res.set(tile);
+		}
+
+		Ok(res)
+	}
+}
+
+/// Only the tiles that took hits are listed, along with how many.
+impl Serialize for DamageMatrix {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let damage: Vec<(Vec2, u8)> = all_tiles().map(|t| (t, self.get(t))).filter(|&(_, d)| d != 0).collect();
+
+		let mut map = serializer.serialize_map(Some(damage.len()))?;
+		for (tile, damage) in &damage {
+			map.serialize_entry(tile, damage)?;
+		}
+
+		map.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for DamageMatrix {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let mut res = DamageMatrix::new();
+		for (tile, damage) in map_entries::<D, Vec2, u8>(deserializer)? {
+			res.set(tile, damage);
+		}
+
+		Ok(res)
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct ShipData {
+	kind: &'static ShipInfo,
+	pos: Vec2,
+	rot: Rotation
+}
+
+impl Serialize for Ship {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		ShipData { kind: self.info, pos: self.state.position(), rot: self.state.rotation() }.serialize(serializer)
+	}
+}
+
+/// How a ship lies depends on the rules, so a ship read on its own is laid out by the classic ones.
+/// Ships read as part of a [`GameState`] are laid out by its rules instead.
+impl<'de> Deserialize<'de> for Ship {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let ShipData { kind, pos, rot } = ShipData::deserialize(deserializer)?;
+		Ok(Ship::new(kind, ShipState::new(pos, rot), &GameRules::default()))
+	}
+}
+
+#[derive(Deserialize)]
+struct GameStateData {
+	players: Vec<PlayerState>,
+	rules: GameRules,
+	turn: Turn,
+	target: Turn,
+	round: u8,
+	phase: Phase
+}
+
+impl<'de> Deserialize<'de> for GameState {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let GameStateData { players, rules, turn, target, round, phase } = GameStateData::deserialize(deserializer)?;
+		GameState::from_parts(players, rules, turn, target, round, phase).map_err(de::Error::custom)
+	}
+}
+
+/// The charges of every weapon, keyed by the weapon. Weapons that aren't listed have none left.
+pub(crate) mod charges {
+	use super::*;
+	use crate::weapon::Weapon;
+
+	pub(crate) fn serialize<S: Serializer>(charges: &[u8; Weapon::COUNT], serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(Weapon::COUNT))?;
+		for weapon in Weapon::ALL {
+			map.serialize_entry(&weapon, &charges[weapon.index()])?;
+		}
+
+		map.end()
+	}
+
+	pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; Weapon::COUNT], D::Error> {
+		let mut res = [0; Weapon::COUNT];
+		for (weapon, charges) in map_entries::<D, Weapon, u8>(deserializer)? {
+			res[weapon.index()] = charges;
+		}
+
+		Ok(res)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::encode::Encode;
+	use crate::rules::ArmorRule;
+
+	use super::*;
+
+	// A battle where both players hit an armored ship, so there are hits and damage to write
+	fn battle() -> GameState {
+		let mut state = GameState::new(&[1, 2], GameRules { armor: ArmorRule::Armored, ..Default::default() }).unwrap();
+		while state.confirm_placement().is_ok_and(|p| p != Phase::Battle) {}
+
+		for _ in 0..2 {
+			let target = state.player(state.target_turn());
+			let ship = target.ships().iter().max_by_key(|s| s.info.armor).unwrap();
+			let tile = ship.tiles().next().unwrap();
+			state.fire(&[tile]).unwrap();
+		}
+
+		state
+	}
+
+	#[test]
+	fn states_round_trip_through_json() {
+		let state = battle();
+		let json = serde_json::to_string(&state).unwrap();
+		assert!(json.contains("\"kind\":\"Carrier\""));
+
+		let read: GameState = serde_json::from_str(&json).unwrap();
+		assert_eq!(read.encode(), state.encode());
+	}
+
+	#[test]
+	fn states_round_trip_through_bincode() {
+		let state = battle();
+		let bytes = bincode::serialize(&state).unwrap();
+
+		let read: GameState = bincode::deserialize(&bytes).unwrap();
+		assert_eq!(read.encode(), state.encode());
+	}
+}
@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use crate::depth::Layer;
+use crate::event::{Effect, GameEvent};
+use crate::game_state::{GameState, PlayerState, ShipInfo, Turn, Vec2};
+use crate::phase::PhaseError;
+use crate::rules::{GameRules, ShotRule, TurnRule, VictoryRule};
+use crate::trap::TrapKind;
+use crate::weapon::Weapon;
+
+/// The outcome of a single shot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShotResult {
+	Miss,
+	Hit,
+	/// The shot hit the last intact tile of a ship.
+	Sunk(&'static ShipInfo),
+	/// The shot hit an armored tile, which needs to be hit again to be destroyed.
+	Damaged,
+	/// The shot hit a mine, which damages the shooter's own fleet.
+	Mine,
+	/// The shot hit a decoy. Unless it is exposed, opponents should only be told it was a hit.
+	Decoy,
+	/// The shot captured the headquarters.
+	Captured
+}
+
+/// Everything that happened during one turn of firing, in the order the shots were given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FireReport {
+	pub shooter: Turn,
+	/// The player who fired, which matters when teammates share a board.
+	pub shooter_id: u64,
+	pub target: Turn,
+	/// The special weapon used instead of regular shots, if any.
+	pub weapon: Option<Weapon>,
+	/// Where the shots went off, which is only below the surface for depth charges.
+	pub layer: Layer,
+	pub shots: Vec<(Vec2, ShotResult)>,
+	/// The tiles of the shooter's own fleet hit by mines.
+	pub backfire: Vec<(Vec2, ShotResult)>,
+	/// Decoys of the target that were exposed this turn.
+	pub exposed: Vec<Vec2>,
+	/// Whether the target is out of the game now, see [`PlayerState::defeat`].
+	pub defeated: bool,
+	/// Whether the game is over, see [`GameState::end`].
+	pub game_over: bool,
+	/// Whether the shooter keeps the turn.
+	pub shoot_again: bool
+}
+
+/// Why the shots of a turn were rejected.
+/// If any shot is invalid, none of them are fired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FireError {
+	WrongShotCount { expected: usize },
+	OutOfBounds(Vec2),
+	AlreadyHit(Vec2),
+	Duplicate(Vec2),
+	/// The weapon has no charges left or isn't allowed by the rules.
+	NoCharges(Weapon),
+	/// Every tile the weapon would hit was already hit.
+	NothingToHit,
+	/// Nothing is submerged in this game, or depth charges aren't allowed by the rules.
+	NoDepthCharges,
+	/// The battle isn't on.
+	Phase(PhaseError)
+}
+
+impl GameState {
+	/// The amount of shots the current player has to fire this turn.
+	pub fn shots_per_turn(&self) -> usize {
+		let (current, target) = self.turns();
+
+		let shots = match self.rules().shots {
+			ShotRule::Single => 1,
+			ShotRule::Salvo => current.ships().iter().filter(|s| !current.is_sunk(s)).count()
+		};
+
+		// Near the end, there may be fewer tiles left than shots
+		let movement = self.rules().movement;
+		let open = self.rules().board_size.tiles().filter(|&t| target.is_open(t, Layer::Surface, movement)).count();
+		shots.min(open)
+	}
+
+	/// Fires the current player's shots at the target.
+	/// Afterwards the turn passes, unless the rules let the shooter fire again.
+	/// Once the game is over, the turn stays with the winner.
+	pub fn fire(&mut self, tiles: &[Vec2]) -> Result<FireReport, FireError> {
+		self.check_shots(tiles)?;
+		Ok(self.resolve_shots(tiles, None, Layer::Surface))
+	}
+
+	/// Checks the current player's shots against the target, without firing them.
+	pub(crate) fn check_shots(&self, tiles: &[Vec2]) -> Result<(), FireError> {
+		self.phase().check_battle().map_err(FireError::Phase)?;
+
+		let expected = self.shots_per_turn();
+		if tiles.len() != expected {
+			return Err(FireError::WrongShotCount { expected });
+		}
+
+		let board_size = self.rules().board_size;
+		let movement = self.rules().movement;
+		for (index, &tile) in tiles.iter().enumerate() {
+			if !board_size.contains(tile) { return Err(FireError::OutOfBounds(tile)); }
+			if !self.target().is_open(tile, Layer::Surface, movement) { return Err(FireError::AlreadyHit(tile)); }
+			if tiles[..index].contains(&tile) { return Err(FireError::Duplicate(tile)); }
+		}
+
+		Ok(())
+	}
+
+	/// Applies already validated shots to the target and passes the turn as needed.
+	pub(crate) fn resolve_shots(&mut self, tiles: &[Vec2], weapon: Option<Weapon>, layer: Layer) -> FireReport {
+		let mut report = self.apply_shots(tiles, weapon, layer);
+
+		if report.game_over {
+			// Nothing left to do
+		} else if report.shoot_again {
+			// The target may have just been knocked out
+			self.retarget();
+		} else {
+			self.pass_turn();
+			// That may have been the last turn of the last round
+			report.game_over = self.is_over();
+		}
+
+		if report.game_over {
+			self.finish();
+		}
+
+		report
+	}
+
+	/// Applies already validated shots to the target, without passing the turn.
+	pub(crate) fn apply_shots(&mut self, tiles: &[Vec2], weapon: Option<Weapon>, layer: Layer) -> FireReport {
+		let rules = self.rules().clone();
+		let shooter = self.turn();
+		let target = self.target_turn();
+
+		let mut shots = Vec::new();
+		let mut exposed = Vec::new();
+		for &tile in tiles {
+			let effect = self.record(GameEvent::ShotFired { shooter, target, tile, layer });
+			let result = effect.result.unwrap_or(ShotResult::Miss);
+			self.log_outcome(target, tile, result);
+
+			shots.push((tile, result));
+			exposed.extend(effect.exposed);
+		}
+
+		let defeated = self.target().is_defeated();
+
+		// Every mine costs the shooter a random intact tile of their fleet
+		let mines = shots.iter().filter(|(_, r)| *r == ShotResult::Mine).count();
+		let mut backfire = Vec::new();
+		for _ in 0..mines {
+			let player = self.current();
+			let intact: Vec<(Vec2, Layer)> = player.ships().iter()
+				.flat_map(|s| s.tiles().map(|t| (t, s.layer())))
+				.filter(|&(t, layer)| !player.is_hit(t, layer))
+				.collect();
+
+			let Some(&(tile, layer)) = intact.choose(&mut thread_rng()) else { break; };
+			let result = self.record(GameEvent::Backfire { player: shooter, tile, layer }).result.unwrap_or(ShotResult::Miss);
+			self.log_outcome(shooter, tile, result);
+			backfire.push((tile, result));
+		}
+
+		let shooter_defeated = self.current().is_defeated();
+		let game_over = self.is_over();
+		let shoot_again = !game_over && !shooter_defeated && match rules.turns {
+			TurnRule::Alternating | TurnRule::Simultaneous => false,
+			TurnRule::ShootAgainOnHit => shots.iter().any(|(_, r)| r.is_hit())
+		};
+
+		let shooter_id = self.current().shooter_id();
+		FireReport { shooter, shooter_id, target, weapon, layer, shots, backfire, exposed, defeated, game_over, shoot_again }
+	}
+
+	/// Fires at a single tile of the target. Traps float on the surface, so only regular shots set them off.
+	/// Shots right next to a decoy give it away, and sinking ships scores points in a points race.
+	pub(crate) fn fire_at(&mut self, shooter: Turn, target: Turn, tile: Vec2, layer: Layer) -> Effect {
+		let rules = self.rules().clone();
+
+		let player = self.player_mut(target);
+		let trap = player.trap_at(tile).filter(|_| layer == Layer::Surface);
+		let result = match trap {
+			None if layer == Layer::Surface && player.headquarters() == Some(tile) => {
+				player.hit(tile, layer);
+				ShotResult::Captured
+			}
+			Some(trap) if trap.kind == TrapKind::Mine => {
+				player.hit(tile, layer);
+				ShotResult::Mine
+			}
+			Some(_) => {
+				player.hit(tile, layer);
+				ShotResult::Decoy
+			}
+			None => shoot_tile(player, tile, layer, &rules)
+		};
+
+		let exposed = player.expose_decoys(|pos| rules.grid().are_neighbours(tile, pos));
+
+		if let (VictoryRule::Points { .. }, ShotResult::Sunk(info)) = (rules.victory, result) {
+			self.player_mut(shooter).add_points(info.tile_count());
+		}
+
+		Effect { result: Some(result), exposed }
+	}
+
+	/// Hits a tile of the player's own fleet, ignoring any traps.
+	pub(crate) fn backfire_at(&mut self, player: Turn, tile: Vec2, layer: Layer) -> ShotResult {
+		let rules = self.rules().clone();
+		shoot_tile(self.player_mut(player), tile, layer, &rules)
+	}
+
+	/// Ends the current player's turn.
+	pub(crate) fn pass_turn(&mut self) {
+		// Teammates take turns firing for their board
+		self.record(GameEvent::TurnEnded { player: self.turn() });
+		self.next_turn();
+	}
+}
+
+// Fires at a tile without any traps, only hitting ships in the given layer
+fn shoot_tile(player: &mut PlayerState, tile: Vec2, layer: Layer, rules: &GameRules) -> ShotResult {
+	// Armor takes the hit first
+	if let Some(ship) = player.overlap(tile).filter(|s| s.layer() == layer) {
+		if player.absorb_hit(tile, rules.armor.armor(ship.info)) {
+			return ShotResult::Damaged;
+		}
+	}
+
+	player.hit(tile, layer);
+
+	match player.overlap(tile).filter(|s| s.layer() == layer) {
+		Some(ref s) if player.is_sunk(s) => {
+			if rules.placement.marks_surroundings() {
+				player.mark_surroundings(s, rules);
+			}
+
+			ShotResult::Sunk(s.info)
+		}
+		Some(_) => ShotResult::Hit,
+		None => ShotResult::Miss
+	}
+}
+
+impl ShotResult {
+	/// Whether the shot looks like a hit to the shooter. Decoys and armor count, mines don't.
+	pub fn is_hit(self) -> bool {
+		matches!(self, ShotResult::Hit | ShotResult::Sunk(_) | ShotResult::Damaged | ShotResult::Decoy | ShotResult::Captured)
+	}
+}
+
+impl FireReport {
+	pub fn hit_count(&self) -> usize {
+		self.shots.iter().filter(|(_, r)| r.is_hit()).count()
+	}
+
+	pub fn sunk(&self) -> impl Iterator<Item = &'static ShipInfo> + '_ {
+		self.shots.iter().filter_map(|&(_, r)| match r {
+			ShotResult::Sunk(info) => Some(info),
+			_ => None
+		})
+	}
+}
+
+impl Display for FireError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			FireError::WrongShotCount { expected: 1 } => write!(f, "You have to fire exactly 1 shot."),
+			FireError::WrongShotCount { expected } => write!(f, "You have to fire exactly {expected} shots."),
+			FireError::OutOfBounds(_) => write!(f, "That coordinate is not on the board."),
+			FireError::AlreadyHit(_) => write!(f, "You already fired at that coordinate."),
+			FireError::Duplicate(_) => write!(f, "You can't fire at the same coordinate twice."),
+			FireError::NoCharges(weapon) => write!(f, "You have no {} left.", weapon.label()),
+			FireError::NothingToHit => write!(f, "Every tile in that area was already hit."),
+			FireError::NoDepthCharges => write!(f, "There are no depth charges in this game."),
+			FireError::Phase(err) => err.fmt(f)
+		}
+	}
+}
+
+impl Error for FireError {}
@@ -0,0 +1,161 @@
+use crate::game_state::{BoardSize, HitMatrix, Vec2};
+
+/// Tiles of a board that ships can't be on. They are the same on every board and everyone can see them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainKind {
+	Island,
+	Reef
+}
+
+/// A hand-made terrain layout, which scales with the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TerrainPreset {
+	/// One island in the middle of the board, guarded by reefs.
+	Atoll,
+	/// Two islands reaching in from the sides, with a narrow passage between them.
+	Strait,
+	/// Reefs spread evenly across the board.
+	Shoals
+}
+
+/// Where the terrain of a board is.
+#[derive(Debug, Clone, Default)]
+pub struct Terrain {
+	islands: HitMatrix,
+	reefs: HitMatrix,
+	count: usize
+}
+
+impl TerrainKind {
+	pub fn label(self) -> &'static str {
+		match self {
+			TerrainKind::Island => "Island",
+			TerrainKind::Reef => "Reef"
+		}
+	}
+}
+
+impl TerrainPreset {
+	pub const COUNT: usize = 3;
+	pub const ALL: [TerrainPreset; Self::COUNT] = [TerrainPreset::Atoll, TerrainPreset::Strait, TerrainPreset::Shoals];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			TerrainPreset::Atoll => "atoll",
+			TerrainPreset::Strait => "strait",
+			TerrainPreset::Shoals => "shoals"
+		}
+	}
+
+	pub fn index(self) -> usize {
+		self as usize
+	}
+
+	pub(crate) fn layout(self, board_size: BoardSize) -> Terrain {
+		let size = board_size.get();
+		let mid = size / 2;
+		let mut res = Terrain::default();
+
+		match self {
+			TerrainPreset::Atoll => {
+				for y in mid - 1..=mid {
+					for x in mid - 1..=mid {
+						res.add(Vec2::new(x, y), TerrainKind::Island);
+					}
+				}
+
+				for (x, y) in [(mid - 3, mid - 3), (mid + 2, mid - 3), (mid - 3, mid + 2), (mid + 2, mid + 2)] {
+					res.add(Vec2::new(x, y), TerrainKind::Reef);
+				}
+			}
+			TerrainPreset::Strait => {
+				let reach = size / 4;
+				for x in 0..reach {
+					res.add(Vec2::new(x, mid - 1), TerrainKind::Island);
+					res.add(Vec2::new(size - 1 - x, mid), TerrainKind::Island);
+				}
+			}
+			TerrainPreset::Shoals => {
+				for y in (2..size - 1).step_by(4) {
+					for x in (2..size - 1).step_by(4) {
+						res.add(Vec2::new(x, y), TerrainKind::Reef);
+					}
+				}
+			}
+		}
+
+		res
+	}
+}
+
+impl Terrain {
+	/// Scatters a few islands and reefs. The same seed always gives the same layout.
+	pub(crate) fn seeded(seed: u16, board_size: BoardSize) -> Self {
+		let size = board_size.get();
+		let mut rng = XorShift::new(seed);
+		let mut res = Terrain::default();
+
+		// About one tile in twenty, so the fleet still has room
+		let target = usize::from(size) * usize::from(size) / 20;
+		while res.count < target {
+			let pos = Vec2::new(rng.below(size), rng.below(size));
+			if rng.below(3) == 0 {
+				res.add(pos, TerrainKind::Reef);
+				continue;
+			}
+
+			// Islands grow a little in a random direction
+			res.add(pos, TerrainKind::Island);
+			let next = match rng.below(4) {
+				0 => Vec2::new(pos.x + 1, pos.y),
+				1 => Vec2::new(pos.x, pos.y + 1),
+				_ => continue
+			};
+
+			if board_size.contains(next) { res.add(next, TerrainKind::Island); }
+		}
+
+		res
+	}
+
+	pub fn kind_at(&self, pos: Vec2) -> Option<TerrainKind> {
+		if self.islands.get(pos) { Some(TerrainKind::Island) }
+		else if self.reefs.get(pos) { Some(TerrainKind::Reef) }
+		else { None }
+	}
+
+	/// The amount of tiles taken up by terrain.
+	pub fn tile_count(&self) -> usize {
+		self.count
+	}
+
+	fn add(&mut self, pos: Vec2, kind: TerrainKind) {
+		if self.kind_at(pos).is_some() { return; }
+
+		match kind {
+			TerrainKind::Island => self.islands.set(pos),
+			TerrainKind::Reef => self.reefs.set(pos)
+		}
+
+		self.count += 1;
+	}
+}
+
+// The layout has to be the same wherever the seed is decoded,
+// so this doesn't rely on the generators of the rand crate.
+struct XorShift(u32);
+
+impl XorShift {
+	fn new(seed: u16) -> Self {
+		// Zero would get stuck, so the seed is mixed into a fixed odd value
+		Self(0x9E37_79B9 ^ u32::from(seed))
+	}
+
+	fn below(&mut self, max: u8) -> u8 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 17;
+		self.0 ^= self.0 << 5;
+		(self.0 % u32::from(max)) as u8
+	}
+}
@@ -0,0 +1,32 @@
+use crate::game_state::Vec2;
+
+/// Something other than a ship hidden on a player's board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trap {
+	pub kind: TrapKind,
+	pub pos: Vec2,
+	/// Whether the opponents know this is a decoy. Mines are known once hit.
+	pub exposed: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrapKind {
+	/// Costs whoever hits it a tile of their own fleet.
+	Mine,
+	/// Pretends to be a ship until it is exposed by a sonar or shots right next to it.
+	Decoy
+}
+
+impl TrapKind {
+	/// The traps every player places, in order.
+	pub const LAYOUT: [TrapKind; 4] = [TrapKind::Mine, TrapKind::Mine, TrapKind::Decoy, TrapKind::Decoy];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			TrapKind::Mine => "Mine",
+			TrapKind::Decoy => "Decoy"
+		}
+	}
+}
@@ -0,0 +1,101 @@
+use crate::event::GameEvent;
+use crate::game_state::{GameState, PlayerState, ShipInfo, Turn, Vec2};
+use crate::rules::VictoryRule;
+
+/// How a player lost and dropped out of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Defeat {
+	/// Every ship of the fleet was sunk.
+	Fleet,
+	/// The secret flagship was sunk.
+	Flagship(&'static ShipInfo),
+	/// The headquarters at the tile were captured.
+	Headquarters(Vec2)
+}
+
+/// Why the game is over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEnd {
+	/// At most one player is left standing.
+	LastStanding,
+	/// The rounds of a points race are up, so the points decide.
+	RoundLimit
+}
+
+impl PlayerState {
+	/// How the player lost, if they did.
+	pub fn defeat(&self) -> Option<Defeat> {
+		if let Some(pos) = self.headquarters().filter(|&pos| self.hits.get(pos)) {
+			return Some(Defeat::Headquarters(pos));
+		}
+
+		if let Some(ship) = self.flagship().filter(|s| self.is_sunk(s)) {
+			return Some(Defeat::Flagship(ship.info));
+		}
+
+		self.are_all_ships_sunk().then_some(Defeat::Fleet)
+	}
+
+	pub fn is_defeated(&self) -> bool {
+		self.defeat().is_some()
+	}
+}
+
+impl GameState {
+	/// Why the game is over, if it is.
+	pub fn end(&self) -> Option<GameEnd> {
+		if self.players().iter().filter(|p| !p.is_defeated()).count() <= 1 {
+			return Some(GameEnd::LastStanding);
+		}
+
+		match self.rules().victory {
+			VictoryRule::Points { rounds } if self.round() >= rounds => Some(GameEnd::RoundLimit),
+			_ => None
+		}
+	}
+
+	pub fn is_over(&self) -> bool {
+		self.end().is_some()
+	}
+
+	/// The last player standing, or the one with the most points once the rounds are up.
+	/// There is none if nobody is left, or if the points are tied.
+	pub fn winner(&self) -> Option<&PlayerState> {
+		self.winner_turn().map(|turn| self.player(turn))
+	}
+
+	/// The turn of the [winner](GameState::winner), if there is one.
+	pub fn winner_turn(&self) -> Option<Turn> {
+		let count = self.players().len();
+		let standing = (0..count)
+			.filter_map(|i| Turn::new(i as u8 + 1, count))
+			.filter(|&t| !self.player(t).is_defeated());
+		let mut leaders: Vec<Turn> = match self.end()? {
+			GameEnd::LastStanding => standing.collect(),
+			GameEnd::RoundLimit => {
+				let best = standing.clone().map(|t| self.player(t).points()).max()?;
+				standing.filter(|&t| self.player(t).points() == best).collect()
+			}
+		};
+
+		match (leaders.pop(), leaders.is_empty()) {
+			(Some(winner), true) => Some(winner),
+			_ => None
+		}
+	}
+
+	/// Finishes the game, once it is over.
+	pub(crate) fn finish(&mut self) {
+		if self.is_over() {
+			self.record(GameEvent::GameWon { winner: self.winner_turn() });
+		}
+	}
+
+	/// The rounds left in a points race, including the current one.
+	pub fn rounds_left(&self) -> Option<u8> {
+		match self.rules().victory {
+			VictoryRule::Points { rounds } => Some(rounds.saturating_sub(self.round())),
+			_ => None
+		}
+	}
+}
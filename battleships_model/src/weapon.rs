@@ -0,0 +1,124 @@
+use crate::depth::Layer;
+use crate::event::GameEvent;
+use crate::game_state::{GameState, Turn, Vec2};
+use crate::rules::{GameRules, TurnRule};
+use crate::shot::{FireError, FireReport};
+
+/// A special weapon that can be used instead of the regular shots of a turn.
+/// Each player only has a limited amount of charges per game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Weapon {
+	/// Reveals whether any intact ship tile lies around the chosen tile and exposes decoys there, without hitting anything.
+	Sonar,
+	/// Hits a segment of a row, going right from the chosen tile.
+	Airstrike,
+	/// Hits the chosen tile and the tiles next to it.
+	Cluster
+}
+
+/// What a sonar ping found. Only the player who used it should get to see this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SonarReport {
+	pub shooter: Turn,
+	pub shooter_id: u64,
+	pub target: Turn,
+	pub center: Vec2,
+	pub found: bool,
+	/// Decoys in the area, which are now exposed to everyone.
+	pub decoys: Vec<Vec2>,
+	/// Whether that was the last turn of a points race.
+	pub game_over: bool
+}
+
+/// The outcome of using a weapon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeaponReport {
+	Sonar(SonarReport),
+	Strike(FireReport)
+}
+
+impl Weapon {
+	pub const COUNT: usize = 3;
+	pub const ALL: [Weapon; Self::COUNT] = [Weapon::Sonar, Weapon::Airstrike, Weapon::Cluster];
+
+	/// The amount of tiles an airstrike covers.
+	pub const AIRSTRIKE_LEN: u8 = 4;
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Weapon::Sonar => "Sonar",
+			Weapon::Airstrike => "Airstrike",
+			Weapon::Cluster => "Cluster Shot"
+		}
+	}
+
+	/// The amount of charges every player starts with.
+	pub fn starting_charges(self) -> u8 {
+		match self {
+			Weapon::Sonar => 2,
+			Weapon::Airstrike => 1,
+			Weapon::Cluster => 1
+		}
+	}
+
+	pub fn index(self) -> usize {
+		self as usize
+	}
+
+	/// The tiles the weapon covers when aimed at `pos`, cut off at the edges of flat boards.
+	pub fn area(self, pos: Vec2, rules: &GameRules) -> Vec<Vec2> {
+		let grid = rules.grid();
+		match self {
+			Weapon::Sonar => grid.surroundings(pos),
+			Weapon::Airstrike => (0..i16::from(Self::AIRSTRIKE_LEN)).filter_map(|dx| grid.offset(pos, dx, 0)).collect(),
+			Weapon::Cluster => std::iter::once(pos).chain(grid.neighbours(pos)).collect()
+		}
+	}
+}
+
+impl GameState {
+	/// Uses one charge of a weapon against the target instead of firing regular shots.
+	/// Like firing, this passes the turn as needed.
+	pub fn use_weapon(&mut self, weapon: Weapon, pos: Vec2) -> Result<WeaponReport, FireError> {
+		self.phase().check_battle().map_err(FireError::Phase)?;
+
+		let board_size = self.rules().board_size;
+		// Weapons take up a whole turn, so they can't be locked in with everyone else's shots
+		if self.current().charges(weapon) == 0 || self.rules().turns == TurnRule::Simultaneous {
+			return Err(FireError::NoCharges(weapon));
+		}
+		if !board_size.contains(pos) { return Err(FireError::OutOfBounds(pos)); }
+
+		let area = weapon.area(pos, self.rules());
+		let (shooter, target) = (self.turn(), self.target_turn());
+		if weapon == Weapon::Sonar {
+			// Submerged ships show up as well
+			let player = self.target();
+			let found = area.iter().any(|&t| player.overlap(t).is_some_and(|s| !player.is_hit(t, s.layer())));
+			let decoys = self.record(GameEvent::WeaponUsed { player: shooter, target, weapon, pos }).exposed;
+
+			let mut report = SonarReport {
+				shooter,
+				shooter_id: self.current().shooter_id(),
+				target,
+				center: pos,
+				found,
+				decoys,
+				game_over: false
+			};
+
+			self.pass_turn();
+			report.game_over = self.is_over();
+			self.finish();
+			return Ok(WeaponReport::Sonar(report));
+		}
+
+		let movement = self.rules().movement;
+		let tiles: Vec<Vec2> = area.into_iter().filter(|&t| self.target().is_open(t, Layer::Surface, movement)).collect();
+		if tiles.is_empty() { return Err(FireError::NothingToHit); }
+
+		self.record(GameEvent::WeaponUsed { player: shooter, target, weapon, pos });
+		Ok(WeaponReport::Strike(self.resolve_shots(&tiles, Some(weapon), Layer::Surface)))
+	}
+}